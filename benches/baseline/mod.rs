@@ -0,0 +1,6 @@
+// The lexer as it was before the rewrite (baseline commit), kept to compare the two.
+// It reads fewer kinds of tokens, what it can't read becomes errors and it goes on.
+#![allow(dead_code)]
+
+pub mod lexer;
+pub mod tokens;
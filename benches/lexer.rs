@@ -0,0 +1,47 @@
+mod baseline;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use oli_script::lexer::Lexer;
+
+// Representative TS/TSX, repeated to get a file of a few hundred KB
+const SNIPPET: &str = r#"
+import React, { useState, useEffect as effect } from 'react';
+import * as path from "path";
+
+/**
+ * Formats a value for display.
+ */
+export function format<T extends object>(value: T, locale = 'en-US', precision?: number) {
+  const factor = 10 ** (precision ?? 2);
+  let total = 0x1F + 1_000_000 + .5e-3 + 10n;
+  if (value !== null && typeof value === "object") {
+    total += Math.round(total * factor) / factor; // rounding
+  }
+  const message = `${locale}: ${total.toFixed(2)} items\n`;
+  return /^[a-z]+\d*$/gi.test(message) ? message : "fallback\t'quoted'";
+}
+
+interface Props { name: string; count: number }
+
+function Greeting({ name, count }: Props) {
+  const [state, setState] = useState<Array<number>>([]);
+  return <div className="greeting" data-count={count}>Hello {name}! <b>{state.length}</b></div>;
+}
+"#;
+
+fn lexer(c: &mut Criterion) {
+  let source = SNIPPET.repeat(500);
+
+  let mut group = c.benchmark_group("lexer");
+  group.throughput(Throughput::Bytes(source.len() as u64));
+  group.bench_function("tokens", |b| {
+    b.iter(|| Lexer::from_text(black_box(&source)).count())
+  });
+  group.bench_function("baseline", |b| {
+    b.iter(|| baseline::lexer::Lexer::from_text(black_box(&source)).count())
+  });
+  group.finish();
+}
+
+criterion_group!(benches, lexer);
+criterion_main!(benches);
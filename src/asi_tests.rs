@@ -0,0 +1,65 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod asi_tests {
+  use crate::ast::asi::{can_insert_semicolon, is_restricted_update, read_semicolon};
+  use crate::cursor::TokenCursor;
+  use crate::lexer::Lexer;
+
+  #[test]
+  fn preceded_by_newline() {
+    let lexer = Lexer::from_text("a b\nc /* \n */ d /* e */ f // g\n`h\n${i}`\r\nj");
+    let result: Vec<bool> = lexer.map(|t| t.preceded_by_newline).collect();
+
+    assert_eq!(
+      result,
+      vec![false, false, true, true, false, true, false, false, true]
+    );
+  }
+
+  #[test]
+  fn preceded_by_newline_with_trivia() {
+    let lexer = Lexer::from_text("a // b\nc").with_trivia();
+    let result: Vec<bool> = lexer.map(|t| t.preceded_by_newline).collect();
+
+    assert_eq!(result, vec![false, true]);
+  }
+
+  #[test]
+  fn semicolon_insertion() {
+    let mut lexer = TokenCursor::new(Lexer::from_text("{ a; b\nc } d e"));
+
+    lexer.next();
+    lexer.next();
+    assert_eq!(read_semicolon(&mut lexer), Ok(()));
+    lexer.next();
+    assert!(can_insert_semicolon(lexer.peek()));
+    assert_eq!(read_semicolon(&mut lexer), Ok(()));
+    lexer.next();
+    // Before `}`
+    assert_eq!(read_semicolon(&mut lexer), Ok(()));
+    lexer.next();
+    lexer.next();
+    assert_eq!(
+      read_semicolon(&mut lexer),
+      Err(String::from("line: 2 col: 7 ';' expected"))
+    );
+    lexer.next();
+    // End of file
+    assert!(can_insert_semicolon(lexer.peek()));
+    assert_eq!(read_semicolon(&mut lexer), Ok(()));
+  }
+
+  #[test]
+  fn restricted_update() {
+    let tokens: Vec<_> = Lexer::from_text("x\n++y x++ z\n--").collect();
+    let restricted: Vec<_> = tokens
+      .iter()
+      .map(|t| is_restricted_update(t.token.kind, t.preceded_by_newline))
+      .collect();
+
+    assert_eq!(
+      restricted,
+      vec![false, true, false, false, false, false, true]
+    );
+  }
+}
@@ -0,0 +1,47 @@
+use crate::cursor::TokenCursor;
+use crate::lexer::LocatedToken;
+use crate::tokens::TokenKind;
+
+/**
+ * Automatic semicolon insertion (ECMAScript 12.10)
+ * A statement that isn't followed by `;` is still complete if the next token:
+ * - is preceded by a line break
+ * - is `}`
+ * - is the end of the file
+ *
+ * The next token must be rejected by the grammar first: `a\n(b)` is a call, not two statements.
+ * A `;` is never inserted in a `for (;;)` header or to create an empty statement.
+ */
+pub fn can_insert_semicolon(next: Option<&LocatedToken>) -> bool {
+  match next {
+    None => true,
+    Some(located_token) => {
+      located_token.preceded_by_newline || located_token.token.kind == TokenKind::CloseBrace
+    }
+  }
+}
+
+/// Reads the `;` ending a statement, inserting it if it's missing and that's allowed.
+pub fn read_semicolon(lexer: &mut TokenCursor) -> Result<(), String> {
+  match lexer.peek() {
+    Some(located_token) if located_token.token.kind == TokenKind::Semicolon => {
+      lexer.next();
+      Ok(())
+    }
+    next if can_insert_semicolon(next) => Ok(()),
+    Some(located_token) => Err(format!(
+      "line: {} col: {} ';' expected",
+      located_token.line, located_token.col
+    )),
+    None => unreachable!(),
+  }
+}
+
+/**
+ * Restricted production: no line break is allowed between an operand and its postfix `++` or `--`,
+ * the expression ends before it instead: x\n++y => x; ++y;
+ * Only asked about a `++` or `--` right after an operand, `a =\n++b` is a prefix `++`.
+ */
+pub fn is_restricted_update(kind: TokenKind, preceded_by_newline: bool) -> bool {
+  preceded_by_newline && matches!(kind, TokenKind::PlusPlus | TokenKind::MinusMinus)
+}
@@ -0,0 +1,635 @@
+use super::asi::is_restricted_update;
+use super::types::{type_definition, TypeDefinition};
+use super::utils::{
+  comma_separated, feed_token, identifier, peek_token, terminal, token, SpannedToken, TokenMatcher,
+};
+use crate::atom::Atom;
+use crate::cursor::TokenCursor;
+use crate::parser::{
+  Associativity, Grammar, MatcherResult, TypedMatcher, TypedOperators, TypedRule,
+};
+use crate::sequence;
+use crate::span::Span;
+use crate::tokens::{Literal, Token, TokenKind, TokenValue};
+use std::fmt;
+
+#[allow(dead_code)] // Not consumed yet, only printed
+#[derive(Debug)]
+pub struct Expression {
+  kind: ExpressionKind,
+  span: Span,
+}
+
+#[derive(Debug)]
+enum ExpressionKind {
+  Identifier(Atom),
+  Number(f64),
+  String(Atom),
+  BigInt(Atom), // As written, with the `n`
+  Regex {
+    pattern: Atom,
+    flags: Atom,
+  },
+  Boolean(bool),
+  Null,
+  This,
+  // -a, !a, typeof a, await a...
+  Unary {
+    operator: TokenKind,
+    operand: Box<Expression>,
+  },
+  // ++a, a--
+  Update {
+    operator: TokenKind,
+    prefix: bool,
+    operand: Box<Expression>,
+  },
+  // Arithmetic, bitwise, comparisons, logical operators
+  Binary {
+    left: Box<Expression>,
+    operator: TokenKind,
+    right: Box<Expression>,
+  },
+  // a = b, a += b...
+  Assignment {
+    target: Box<Expression>,
+    operator: TokenKind,
+    value: Box<Expression>,
+  },
+  Conditional {
+    test: Box<Expression>,
+    consequent: Box<Expression>,
+    alternate: Box<Expression>,
+  },
+  // f(a), f?.(a)
+  Call {
+    callee: Box<Expression>,
+    arguments: Vec<Expression>,
+    optional: bool,
+  },
+  // a.b, a?.b
+  Member {
+    object: Box<Expression>,
+    property: Atom,
+    optional: bool,
+  },
+  // a[b], a?.[b]
+  Index {
+    object: Box<Expression>,
+    index: Box<Expression>,
+    optional: bool,
+  },
+  NonNull(Box<Expression>), // a!
+  As {
+    expression: Box<Expression>,
+    definition: TypeDefinition,
+  },
+  Satisfies {
+    expression: Box<Expression>,
+    definition: TypeDefinition,
+  },
+}
+
+impl Expression {
+  fn new(kind: ExpressionKind, span: Span) -> Self {
+    Expression { kind, span }
+  }
+
+  /// Reads an expression, up to the first token that can't go on with it,
+  /// with a grammar from `expression_grammar`
+  pub fn create<'a>(
+    (grammar, expression): &ExpressionGrammar<'a>,
+    lexer: &mut TokenCursor<'a>,
+  ) -> Option<Result<Self, String>> {
+    let (token, ..) = peek_token(lexer).ok()?;
+
+    let mut parser = grammar.typed_matcher(expression);
+    let mut last = match parser.next(&token) {
+      MatcherResult::Rejected => return None,
+      MatcherResult::End(v) | MatcherResult::Value(v) => Some(v),
+      MatcherResult::Accepted => None,
+    };
+    lexer.next();
+
+    let result = loop {
+      match (feed_token(&mut parser, lexer), &last) {
+        (Ok(MatcherResult::End(v)), _) => break Ok(parser.value(&v)),
+        (Ok(MatcherResult::Value(v)), _) => last = Some(v),
+        (Ok(_), _) => last = None,
+        (Err(_), Some(v)) => break Ok(parser.value(v)),
+        (Err(r), None) => break Err(r),
+      }
+    };
+    Some(result)
+  }
+}
+
+pub type ExpressionGrammar<'a> = (
+  Grammar<'a, SpannedToken<'a>>,
+  TypedRule<'a, SpannedToken<'a>, Expression>,
+);
+
+/// Built once for all the expressions read with it, operators make it a large one
+pub fn expression_grammar<'a>() -> ExpressionGrammar<'a> {
+  let mut builder = Grammar::builder();
+  let expression_rule = builder.declare_typed("expression");
+  let type_rule = builder.declare_typed("type");
+
+  let (expression_ref, type_ref) = (
+    builder.typed_rule(&expression_rule),
+    builder.typed_rule(&type_rule),
+  );
+  builder.define_typed(&expression_rule, expression(&expression_ref, &type_ref));
+  builder.define_typed(&type_rule, type_definition(&type_ref));
+
+  let grammar = builder.build().expect("Invalid expression grammar");
+  (grammar, expression_rule)
+}
+
+// The tokens of an operator
+type TokenKinds = fn(&SpannedToken) -> bool;
+
+// Precedence of the operators, the higher binds tighter
+const ASSIGNMENT: u8 = 3; // `?:` too, its branches are assignments: a ? b : c = d
+const COALESCE: u8 = 4;
+const LOGICAL_OR: u8 = 5;
+const LOGICAL_AND: u8 = 6;
+const RELATIONAL: u8 = 11; // `as` and `satisfies` too
+const EXPONENT: u8 = 15;
+const UNARY: u8 = 16;
+const UPDATE: u8 = 17; // Prefix too: ++a ** b is fine, -a ** b isn't
+const MEMBER: u8 = 18; // Calls and `!` too
+
+/**
+ * Operators and what they start with aren't labeled: an error after `f(a` lists `,` and `)`,
+ * not every operator that could go on with the expression.
+ */
+pub fn expression<'a>(
+  expression: &TokenMatcher<'a, Expression>,
+  type_definition: &TokenMatcher<'a, TypeDefinition>,
+) -> TokenMatcher<'a, Expression> {
+  use Associativity::{Left, Right};
+  use TokenKind::*;
+
+  let binary: [(u8, Associativity, TokenKinds); 12] = [
+    (COALESCE, Left, |t| t.kind == QuestionQuestion),
+    (LOGICAL_OR, Left, |t| t.kind == BarBar),
+    (LOGICAL_AND, Left, |t| t.kind == AmpersandAmpersand),
+    (7, Left, |t| t.kind == Bar),
+    (8, Left, |t| t.kind == Caret),
+    (9, Left, |t| t.kind == Ampersand),
+    (10, Left, |t| {
+      matches!(
+        t.kind,
+        EqualsEquals | ExclamationEquals | EqualsEqualsEquals | ExclamationEqualsEquals
+      )
+    }),
+    (RELATIONAL, Left, |t| {
+      matches!(
+        t.kind,
+        LessThan | GreaterThan | LessThanEquals | GreaterThanEquals | Instanceof | In
+      )
+    }),
+    (12, Left, |t| {
+      matches!(
+        t.kind,
+        LessThanLessThan | GreaterThanGreaterThan | GreaterThanGreaterThanGreaterThan
+      )
+    }),
+    (13, Left, |t| matches!(t.kind, Plus | Minus)),
+    (14, Left, |t| matches!(t.kind, Asterisk | Slash | Percent)),
+    (EXPONENT, Right, |t| t.kind == AsteriskAsterisk),
+  ];
+
+  let mut operators = TypedOperators::new(primary(expression).label("expression"));
+  for (precedence, associativity, kinds) in binary {
+    operators = operators.infix(
+      precedence,
+      associativity,
+      terminal(kinds),
+      |left, op, right| {
+        let span = left.span.to(right.span);
+        let (left, right) = (Box::new(left), Box::new(right));
+        let operator = op.kind;
+        Expression::new(
+          ExpressionKind::Binary {
+            left,
+            operator,
+            right,
+          },
+          span,
+        )
+      },
+    );
+    // Syntax errors without parentheses
+    operators = match precedence {
+      COALESCE => operators.excluding(LOGICAL_OR).excluding(LOGICAL_AND), // a ?? b || c
+      EXPONENT => operators.excluding_left(UNARY),                        // -a ** b
+      _ => operators,
+    };
+  }
+
+  operators
+    .infix(
+      ASSIGNMENT,
+      Right,
+      terminal(is_assignment),
+      |target, op, value| {
+        let span = target.span.to(value.span);
+        let (target, value) = (Box::new(target), Box::new(value));
+        let operator = op.kind;
+        Expression::new(
+          ExpressionKind::Assignment {
+            target,
+            operator,
+            value,
+          },
+          span,
+        )
+      },
+    )
+    .infix(
+      ASSIGNMENT,
+      Right,
+      sequence!(
+        terminal(|t| t.kind == Question),
+        expression.clone(),
+        token!(Colon)
+      )
+      .map(|(_, consequent, _)| consequent),
+      |test, consequent, alternate| {
+        let span = test.span.to(alternate.span);
+        let (test, consequent, alternate) =
+          (Box::new(test), Box::new(consequent), Box::new(alternate));
+        Expression::new(
+          ExpressionKind::Conditional {
+            test,
+            consequent,
+            alternate,
+          },
+          span,
+        )
+      },
+    )
+    .prefix(
+      UNARY,
+      terminal(|t| {
+        matches!(
+          t.kind,
+          Exclamation | Tilde | Plus | Minus | Typeof | Void | Delete | Await
+        )
+      }),
+      |op, operand| {
+        let span = op.span.to(operand.span);
+        let operator = op.kind;
+        Expression::new(
+          ExpressionKind::Unary {
+            operator,
+            operand: Box::new(operand),
+          },
+          span,
+        )
+      },
+    )
+    .prefix(
+      UPDATE,
+      terminal(|t| matches!(t.kind, PlusPlus | MinusMinus)),
+      |op, operand| {
+        let span = op.span.to(operand.span);
+        let (operator, operand) = (op.kind, Box::new(operand));
+        Expression::new(
+          ExpressionKind::Update {
+            operator,
+            prefix: true,
+            operand,
+          },
+          span,
+        )
+      },
+    )
+    .postfix(
+      UPDATE,
+      terminal(|t| {
+        matches!(t.kind, PlusPlus | MinusMinus)
+          && !is_restricted_update(t.kind, t.preceded_by_newline)
+      }),
+      |operand, op| {
+        let span = operand.span.to(op.span);
+        let (operator, operand) = (op.kind, Box::new(operand));
+        Expression::new(
+          ExpressionKind::Update {
+            operator,
+            prefix: false,
+            operand,
+          },
+          span,
+        )
+      },
+    )
+    .postfix(
+      RELATIONAL,
+      sequence!(
+        terminal(|t| matches!(t.kind, As | Satisfies)),
+        type_definition.clone()
+      ),
+      |expression, (op, definition)| {
+        let span = expression.span.to(definition.span());
+        let expression = Box::new(expression);
+        let kind = match op.kind {
+          As => ExpressionKind::As {
+            expression,
+            definition,
+          },
+          _ => ExpressionKind::Satisfies {
+            expression,
+            definition,
+          },
+        };
+        Expression::new(kind, span)
+      },
+    )
+    .postfix(
+      MEMBER,
+      terminal(|t| t.kind == Exclamation),
+      |operand, op| {
+        let span = operand.span.to(op.span);
+        Expression::new(ExpressionKind::NonNull(Box::new(operand)), span)
+      },
+    )
+    .postfix(MEMBER, access(expression, false), |object, access| {
+      access.apply(object, false)
+    })
+    .postfix(
+      MEMBER,
+      sequence!(
+        terminal(|t| t.kind == QuestionDot),
+        access(expression, true)
+      )
+      .map(|(_, access)| access),
+      |object, access| access.apply(object, true),
+    )
+    .matcher()
+}
+
+// Identifier, literal or (expression)
+fn primary<'a>(expression: &TokenMatcher<'a, Expression>) -> TokenMatcher<'a, Expression> {
+  // TODO templates, array and object literals, functions, classes, JSX...
+  TypedMatcher::one_of(vec![
+    identifier()
+      .map_with_span(|name, span| Expression::new(ExpressionKind::Identifier(name), span)),
+    terminal(|token| {
+      matches!(
+        token.kind,
+        TokenKind::NumericLiteral
+          | TokenKind::StringLiteral
+          | TokenKind::BigIntLiteral
+          | TokenKind::RegexLiteral
+          | TokenKind::True
+          | TokenKind::False
+          | TokenKind::Null
+          | TokenKind::This
+      )
+    })
+    .map(|token| Expression::new(literal(&token), token.span)),
+    // The span of what's inside the parentheses
+    sequence!(token!(OpenParen), expression.clone(), token!(CloseParen))
+      .map(|(_, expression, _)| expression),
+  ])
+}
+
+fn literal(token: &Token) -> ExpressionKind {
+  match (token.kind, &token.value) {
+    (_, Some(TokenValue::Literal(Literal::Decimal(value, _)))) => ExpressionKind::Number(*value),
+    (_, Some(TokenValue::Literal(Literal::Str(value, _)))) => {
+      ExpressionKind::String(Atom::new(value))
+    }
+    (_, Some(TokenValue::Literal(Literal::BigInt(raw)))) => ExpressionKind::BigInt(Atom::new(raw)),
+    (_, Some(TokenValue::Literal(Literal::Regex(pattern, flags)))) => ExpressionKind::Regex {
+      pattern: Atom::new(pattern),
+      flags: Atom::new(flags),
+    },
+    (TokenKind::True, _) => ExpressionKind::Boolean(true),
+    (TokenKind::False, _) => ExpressionKind::Boolean(false),
+    (TokenKind::Null, _) => ExpressionKind::Null,
+    (TokenKind::This, _) => ExpressionKind::This,
+    _ => unreachable!(),
+  }
+}
+
+fn is_assignment(token: &SpannedToken) -> bool {
+  use TokenKind::*;
+  matches!(
+    token.kind,
+    Equals
+      | PlusEquals
+      | MinusEquals
+      | AsteriskEquals
+      | AsteriskAsteriskEquals
+      | SlashEquals
+      | PercentEquals
+      | LessThanLessThanEquals
+      | GreaterThanGreaterThanEquals
+      | GreaterThanGreaterThanGreaterThanEquals
+      | AmpersandEquals
+      | BarEquals
+      | CaretEquals
+      | AmpersandAmpersandEquals
+      | BarBarEquals
+      | QuestionQuestionEquals
+  )
+}
+
+// What follows an object: .property, [index] or (arguments), where it ends
+enum Access {
+  Property(Atom, Span),
+  Index(Expression, Span),
+  Arguments(Vec<Expression>, Span),
+}
+
+impl Access {
+  fn apply(self, object: Expression, optional: bool) -> Expression {
+    let object = Box::new(object);
+    let (kind, end) = match self {
+      Access::Property(property, end) => (
+        ExpressionKind::Member {
+          object,
+          property,
+          optional,
+        },
+        end,
+      ),
+      Access::Index(index, end) => {
+        let index = Box::new(index);
+        (
+          ExpressionKind::Index {
+            object,
+            index,
+            optional,
+          },
+          end,
+        )
+      }
+      Access::Arguments(arguments, end) => (
+        ExpressionKind::Call {
+          callee: object,
+          arguments,
+          optional,
+        },
+        end,
+      ),
+    };
+    let span = match &kind {
+      ExpressionKind::Member { object, .. }
+      | ExpressionKind::Index { object, .. }
+      | ExpressionKind::Call { callee: object, .. } => object.span.to(end),
+      _ => unreachable!(),
+    };
+    Expression::new(kind, span)
+  }
+}
+
+/**
+ * `a.b`, `a[b]` or `a(b)`. After `?.` the property needs no dot, and the tokens are labeled:
+ * they're all that can come next.
+ */
+fn access<'a>(
+  expression: &TokenMatcher<'a, Expression>,
+  after_question_dot: bool,
+) -> TokenMatcher<'a, Access> {
+  let (open_bracket, open_paren) = if after_question_dot {
+    (token!(OpenBracket), token!(OpenParen))
+  } else {
+    (
+      terminal(|t| t.kind == TokenKind::OpenBracket),
+      terminal(|t| t.kind == TokenKind::OpenParen),
+    )
+  };
+  let property = if after_question_dot {
+    property_name().map_with_span(Access::Property)
+  } else {
+    sequence!(terminal(|t| t.kind == TokenKind::Dot), property_name())
+      .map_with_span(|(_, name), span| Access::Property(name, span))
+  };
+
+  TypedMatcher::one_of(vec![
+    property,
+    sequence!(open_bracket, expression.clone(), token!(CloseBracket))
+      .map_with_span(|(_, index, _), span| Access::Index(index, span)),
+    sequence!(
+      open_paren,
+      comma_separated(expression.clone()).optional(),
+      token!(CloseParen)
+    )
+    .map_with_span(|(_, arguments, _), span| {
+      Access::Arguments(arguments.unwrap_or_default(), span)
+    }),
+  ])
+}
+
+// Any identifier or keyword: `a.default`, `a.class`
+fn property_name<'a>() -> TokenMatcher<'a, Atom> {
+  terminal(|token| {
+    token.kind.is_identifier()
+      || token.kind.is_reserved_word()
+      || token.kind.is_strict_mode_reserved_word()
+  })
+  .map(|token| {
+    token
+      .name()
+      .unwrap_or_else(|| Atom::new(token.kind.as_str()))
+  })
+  .label("property name")
+}
+
+/// Every operation in parentheses: `(a + (b * c))`
+impl fmt::Display for Expression {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let question_dot = |optional: &bool| if *optional { "?." } else { "" };
+    match &self.kind {
+      ExpressionKind::Identifier(name) => write!(f, "{}", name),
+      ExpressionKind::Number(value) => write!(f, "{}", value),
+      ExpressionKind::String(value) => write!(f, "{:?}", value.as_str()),
+      ExpressionKind::BigInt(raw) => write!(f, "{}", raw),
+      ExpressionKind::Regex { pattern, flags } => write!(f, "/{}/{}", pattern, flags),
+      ExpressionKind::Boolean(value) => write!(f, "{}", value),
+      ExpressionKind::Null => f.write_str("null"),
+      ExpressionKind::This => f.write_str("this"),
+      ExpressionKind::Unary { operator, operand } => {
+        let text = operator.as_str();
+        let space = if text.chars().all(char::is_alphabetic) {
+          " "
+        } else {
+          ""
+        };
+        write!(f, "({}{}{})", text, space, operand)
+      }
+      ExpressionKind::Update {
+        operator,
+        prefix: true,
+        operand,
+      } => {
+        write!(f, "({}{})", operator.as_str(), operand)
+      }
+      ExpressionKind::Update {
+        operator, operand, ..
+      } => write!(f, "({}{})", operand, operator.as_str()),
+      ExpressionKind::Binary {
+        left,
+        operator,
+        right,
+      }
+      | ExpressionKind::Assignment {
+        target: left,
+        operator,
+        value: right,
+      } => {
+        write!(f, "({} {} {})", left, operator.as_str(), right)
+      }
+      ExpressionKind::Conditional {
+        test,
+        consequent,
+        alternate,
+      } => {
+        write!(f, "({} ? {} : {})", test, consequent, alternate)
+      }
+      ExpressionKind::Call {
+        callee,
+        arguments,
+        optional,
+      } => {
+        write!(f, "{}{}(", callee, question_dot(optional))?;
+        for (i, argument) in arguments.iter().enumerate() {
+          let separator = if i > 0 { ", " } else { "" };
+          write!(f, "{}{}", separator, argument)?;
+        }
+        f.write_str(")")
+      }
+      ExpressionKind::Member {
+        object,
+        property,
+        optional,
+      } => {
+        let dot = if *optional { "?." } else { "." };
+        write!(f, "{}{}{}", object, dot, property)
+      }
+      ExpressionKind::Index {
+        object,
+        index,
+        optional,
+      } => {
+        write!(f, "{}{}[{}]", object, question_dot(optional), index)
+      }
+      ExpressionKind::NonNull(expression) => write!(f, "{}!", expression),
+      ExpressionKind::As {
+        expression,
+        definition,
+      } => write!(f, "({} as {})", expression, definition),
+      ExpressionKind::Satisfies {
+        expression,
+        definition,
+      } => {
+        write!(f, "({} satisfies {})", expression, definition)
+      }
+    }
+  }
+}
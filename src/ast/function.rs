@@ -1,26 +1,28 @@
-use crate::ast::utils::{peek_token, read_token};
-use crate::parser::{
-  Loop, MatchResultValue, MatcherResult, MatcherType, Optional, Sequence, Terminal,
-};
-use crate::tokens::Token;
-use crate::Lexer;
-use crate::{unwrap_enum, unwrap_match};
-use core::iter::Peekable;
-use std::ops::Deref;
-
+use super::expression::{expression, Expression};
+use super::types::{type_definition, TypeDefinition};
+use crate::ast::utils::{feed_token, identifier, peek_token, token, SpannedToken, TokenMatcher};
+use crate::atom::Atom;
+use crate::cursor::TokenCursor;
+use crate::parser::{Grammar, MatcherResult, TypedRule};
+use crate::sequence;
+use crate::span::Span;
+
+#[allow(dead_code)] // Not consumed yet, only printed
 #[derive(Debug)]
 pub struct FunctionDeclaration {
-  identifier: String,
+  identifier: Atom,
   generics: Vec<FunctionGeneric>,
   parameters: Vec<FunctionParameter>,
   // body
+  span: Span,
 }
 
 impl FunctionDeclaration {
-  pub fn create(lexer: &mut Peekable<Lexer>) -> Option<Result<Self, String>> {
+  pub fn create(lexer: &mut TokenCursor) -> Option<Result<Self, String>> {
     let (token, ..) = peek_token(lexer).ok()?;
 
-    let mut parser = function_declaration();
+    let (grammar, function) = function_grammar();
+    let mut parser = grammar.typed_matcher(&function);
     let parser_result = parser.next(&token);
 
     if matches!(parser_result, MatcherResult::Rejected) {
@@ -29,188 +31,121 @@ impl FunctionDeclaration {
     lexer.next();
 
     let result = loop {
-      let (token, line, col) = match read_token(lexer) {
-        Ok(v) => v,
+      match feed_token(&mut parser, lexer) {
+        Ok(MatcherResult::End(v)) => break Ok(parser.value(&v)),
+        Ok(_) => {}
         Err(r) => break Err(r),
-      };
-
-      match parser.next(&token) {
-        MatcherResult::Rejected => {
-          break Err(format!(
-            "line: {} col: {} unexpected token {:?}",
-            line, col, token
-          ))
-        }
-        MatcherResult::End(v) => break Ok(parse_function_declaration(&v)),
-        _ => {}
       }
     };
     Some(result)
   }
 }
 
-fn function_declaration() -> MatcherType<Token> {
-  Sequence::matcher(vec![
-    Terminal::matcher(|token| (matches!(token, Token::Keyword(x) if x == "function"))),
-    Terminal::matcher(|token| (matches!(token, Token::Identifier(_)))),
-    Optional::matcher(function_generics()),
-    function_parameters(),
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == "{"))),
+type FunctionGrammar<'a> = (
+  Grammar<'a, SpannedToken<'a>>,
+  TypedRule<'a, SpannedToken<'a>, FunctionDeclaration>,
+);
+
+/// Types and expressions nest, they are rules the other rules refer to
+fn function_grammar<'a>() -> FunctionGrammar<'a> {
+  let mut builder = Grammar::builder();
+  let function = builder.declare_typed("function declaration");
+  let type_rule = builder.declare_typed("type");
+  let expression_rule = builder.declare_typed("expression");
+
+  let (type_ref, expression_ref) = (
+    builder.typed_rule(&type_rule),
+    builder.typed_rule(&expression_rule),
+  );
+  builder.define_typed(&function, function_declaration(&type_ref, &expression_ref));
+  builder.define_typed(&type_rule, type_definition(&type_ref));
+  builder.define_typed(&expression_rule, expression(&expression_ref, &type_ref));
+
+  let grammar = builder.build().expect("Invalid function grammar");
+  (grammar, function)
+}
+
+fn function_declaration<'a>(
+  type_definition: &TokenMatcher<'a, TypeDefinition>,
+  expression: &TokenMatcher<'a, Expression>,
+) -> TokenMatcher<'a, FunctionDeclaration> {
+  sequence!(
+    token!(Function),
+    identifier(),
+    function_generics(type_definition).optional(),
+    function_parameters(type_definition, expression),
+    token!(OpenBrace),
     // TODO body
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == "}"))),
-  ])
-}
-fn parse_function_declaration(value: &MatchResultValue<Token>) -> FunctionDeclaration {
-  let seq = unwrap_enum!(value, MatchResultValue::Vector);
-  let identifier = unwrap_match!(seq[1], MatchResultValue::Token(Token::Identifier(i)) => i);
-  let generics = match unwrap_enum!(seq[2], MatchResultValue::Option) {
-    Some(v) => parse_function_generics(v.deref()),
-    None => vec![],
-  };
-  let parameters = parse_function_parameters(&seq[3]);
-
-  FunctionDeclaration {
-    identifier: identifier.clone(),
-    generics,
-    parameters,
-  }
-}
-
+    token!(CloseBrace),
+  )
+  .map_with_span(
+    |(_, identifier, generics, parameters, _, _), span| FunctionDeclaration {
+      identifier,
+      generics: generics.unwrap_or_default(),
+      parameters,
+      span,
+    },
+  )
+}
+
+#[allow(dead_code)] // Not consumed yet, only printed
 #[derive(Debug)]
 struct FunctionGeneric {
-  identifier: String,
+  identifier: Atom,
   extends: Option<TypeDefinition>,
+  span: Span,
 }
 
-fn function_generics() -> MatcherType<Token> {
-  Sequence::matcher(vec![
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == "<"))),
-    Loop::matcher(Sequence::matcher(vec![
-      Terminal::matcher(|token| (matches!(token, Token::Identifier(_)))),
-      Optional::matcher(Sequence::matcher(vec![
-        Terminal::matcher(|token| (matches!(token, Token::Keyword(x) if x == "extends"))),
-        type_definition(),
-      ])),
-      Optional::matcher(Terminal::matcher(
-        |token| (matches!(token, Token::Symbol(x) if x == ",")),
-      )),
-    ])),
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == ">"))),
-  ])
-}
-fn parse_function_generics(value: &MatchResultValue<Token>) -> Vec<FunctionGeneric> {
-  let loop_match_result = &unwrap_enum!(value, MatchResultValue::Vector)[1];
-  let loop_results = unwrap_enum!(loop_match_result, MatchResultValue::Vector);
-
-  loop_results
-    .into_iter()
-    .map(|loop_match| {
-      let seq = unwrap_enum!(loop_match, MatchResultValue::Vector);
-      let identifier = unwrap_match!(seq[0], MatchResultValue::Token(Token::Identifier(i)) => i);
-      let extends = match unwrap_enum!(seq[1], MatchResultValue::Option) {
-        Some(v) => Some(parse_definition(
-          &unwrap_enum!(v.deref(), MatchResultValue::Vector)[1],
-        )),
-        None => None,
-      };
-
-      FunctionGeneric {
-        identifier: identifier.clone(),
-        extends,
-      }
-    })
-    .collect()
+fn function_generics<'a>(
+  type_definition: &TokenMatcher<'a, TypeDefinition>,
+) -> TokenMatcher<'a, Vec<FunctionGeneric>> {
+  let generic = sequence!(
+    identifier(),
+    sequence!(token!(Extends), type_definition.clone()).optional(),
+  )
+  .map_with_span(|(identifier, extends), span| FunctionGeneric {
+    identifier,
+    extends: extends.map(|(_, extends)| extends),
+    span,
+  });
+
+  // The comma isn't part of the generic's span
+  let generic = sequence!(generic, token!(Comma).optional()).map(|(generic, _)| generic);
+  sequence!(token!(LessThan), generic.repeat(), token!(GreaterThan))
+    .map(|(_, generics, _)| generics)
 }
 
+#[allow(dead_code)] // Not consumed yet, only printed
 #[derive(Debug)]
 struct FunctionParameter {
-  identifier: String,
+  identifier: Atom,
   definition: Option<TypeDefinition>,
   optional: bool,
   initializer: Option<Expression>,
-}
-
-fn function_parameters() -> MatcherType<Token> {
-  Sequence::matcher(vec![
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == "("))),
-    Loop::matcher(Sequence::matcher(vec![
-      Terminal::matcher(|token| (matches!(token, Token::Identifier(_)))),
-      Optional::matcher(Terminal::matcher(
-        |token| (matches!(token, Token::Symbol(x) if x == "?")),
-      )),
-      Optional::matcher(Sequence::matcher(vec![
-        Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == ":"))),
-        type_definition(),
-      ])),
-      Optional::matcher(Sequence::matcher(vec![
-        Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == "="))),
-        expression(),
-      ])),
-      Optional::matcher(Terminal::matcher(
-        |token| (matches!(token, Token::Symbol(x) if x == ",")),
-      )),
-    ])),
-    Terminal::matcher(|token| (matches!(token, Token::Symbol(x) if x == ")"))),
-  ])
-}
-
-fn parse_function_parameters(value: &MatchResultValue<Token>) -> Vec<FunctionParameter> {
-  let loop_match_result = &unwrap_enum!(value, MatchResultValue::Vector)[1];
-  let loop_results = unwrap_enum!(loop_match_result, MatchResultValue::Vector);
-
-  loop_results
-    .into_iter()
-    .map(|loop_match| {
-      let seq = unwrap_enum!(loop_match, MatchResultValue::Vector);
-      let identifier = unwrap_match!(seq[0], MatchResultValue::Token(Token::Identifier(i)) => i);
-      let optional = unwrap_enum!(seq[1], MatchResultValue::Option);
-      let definition = match unwrap_enum!(seq[2], MatchResultValue::Option) {
-        Some(v) => Some(parse_definition(
-          &unwrap_enum!(v.deref(), MatchResultValue::Vector)[1],
-        )),
-        None => None,
-      };
-      let initializer = match unwrap_enum!(seq[2], MatchResultValue::Option) {
-        Some(v) => Some(parse_expression(
-          &unwrap_enum!(v.deref(), MatchResultValue::Vector)[1],
-        )),
-        None => None,
-      };
-
-      FunctionParameter {
-        identifier: identifier.clone(),
-        optional: matches!(optional, Some(_)),
-        definition,
-        initializer,
-      }
-    })
-    .collect()
-}
-
-// to be declared on external files
-#[derive(Debug)]
-struct TypeDefinition {
-  // TODO
-}
-
-fn type_definition() -> MatcherType<Token> {
-  // TODO
-  Terminal::matcher(|token| (matches!(token, Token::Identifier(_))))
-}
-fn parse_definition(_: &MatchResultValue<Token>) -> TypeDefinition {
-  TypeDefinition {}
-}
-
-#[derive(Debug)]
-struct Expression {
-  // TODO
-}
-
-fn expression() -> MatcherType<Token> {
-  // TODO
-  Terminal::matcher(|token| (matches!(token, Token::Identifier(_))))
-}
-
-fn parse_expression(_: &MatchResultValue<Token>) -> Expression {
-  Expression {}
+  span: Span,
+}
+
+fn function_parameters<'a>(
+  type_definition: &TokenMatcher<'a, TypeDefinition>,
+  expression: &TokenMatcher<'a, Expression>,
+) -> TokenMatcher<'a, Vec<FunctionParameter>> {
+  let parameter = sequence!(
+    identifier(),
+    token!(Question).optional(),
+    sequence!(token!(Colon), type_definition.clone()).optional(),
+    sequence!(token!(Equals), expression.clone()).optional(),
+  )
+  .map_with_span(
+    |(identifier, optional, definition, initializer), span| FunctionParameter {
+      identifier,
+      definition: definition.map(|(_, definition)| definition),
+      optional: optional.is_some(),
+      initializer: initializer.map(|(_, initializer)| initializer),
+      span,
+    },
+  );
+
+  let parameter = sequence!(parameter, token!(Comma).optional()).map(|(parameter, _)| parameter);
+  sequence!(token!(OpenParen), parameter.repeat(), token!(CloseParen))
+    .map(|(_, parameters, _)| parameters)
 }
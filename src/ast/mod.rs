@@ -1,4 +1,7 @@
+pub mod asi;
+pub mod expression;
 mod function;
 mod imports;
 pub mod source_file;
+mod types;
 mod utils;
@@ -1,35 +1,42 @@
+use super::asi::read_semicolon;
 use super::function::FunctionDeclaration;
 use super::imports::ImportDeclaration;
-use crate::lexer::Lexer;
-use crate::tokens::Token;
-use core::iter::Peekable;
+use crate::cursor::TokenCursor;
+use crate::lexer::{LexDiagnostic, Lexer};
+use crate::tokens::TokenKind;
 
 /// SourceFile
+#[allow(dead_code)] // Not consumed yet, only printed
 #[derive(Debug)]
 pub struct SourceFile {
   children: Vec<SourceFileElement>,
+  diagnostics: Vec<LexDiagnostic>, // Tokens that couldn't be read, the parser skipped them
 }
 
+#[allow(dead_code)] // Not consumed yet, only printed
 #[derive(Debug)]
 enum SourceFileElement {
   ImportDeclaration(ImportDeclaration),
   FunctionDeclaration(FunctionDeclaration),
 }
 
-impl From<Lexer> for SourceFile {
+impl From<Lexer<'_>> for SourceFile {
   fn from(lexer: Lexer) -> Self {
-    let mut peekable = lexer.peekable();
+    let mut cursor = TokenCursor::new(lexer);
 
     let mut children = vec![];
 
     loop {
-      while next_is_semicolon(&mut peekable) {
-        peekable.next();
+      while next_is_semicolon(&cursor) {
+        cursor.next();
       }
 
-      let result = ImportDeclaration::create(&mut peekable);
+      let result = ImportDeclaration::create(&mut cursor);
       match result {
         Some(Ok(v)) => {
+          if let Err(r) = read_semicolon(&mut cursor) {
+            panic!("Error parsing import: {}", r);
+          }
           children.push(SourceFileElement::ImportDeclaration(v));
           continue;
         }
@@ -39,7 +46,7 @@ impl From<Lexer> for SourceFile {
         _ => {}
       };
 
-      let result = FunctionDeclaration::create(&mut peekable);
+      let result = FunctionDeclaration::create(&mut cursor);
       match result {
         Some(Ok(v)) => {
           children.push(SourceFileElement::FunctionDeclaration(v));
@@ -54,10 +61,13 @@ impl From<Lexer> for SourceFile {
       break;
     }
 
-    SourceFile { children }
+    SourceFile {
+      children,
+      diagnostics: cursor.lexer().diagnostics().to_vec(),
+    }
   }
 }
 
-fn next_is_semicolon(peekable: &mut Peekable<Lexer>) -> bool {
-  matches!(peekable.peek(), Some(located_token) if matches!(&located_token.token, Ok(Token::Symbol(s)) if s == ";"))
+fn next_is_semicolon(cursor: &TokenCursor) -> bool {
+  matches!(cursor.peek(), Some(located_token) if located_token.token.kind == TokenKind::Semicolon)
 }
@@ -0,0 +1,57 @@
+use super::utils::{comma_separated, identifier, token, TokenMatcher};
+use crate::atom::Atom;
+use crate::sequence;
+use crate::span::Span;
+use std::fmt;
+
+#[allow(dead_code)] // Not consumed yet, only printed
+#[derive(Debug)]
+pub struct TypeDefinition {
+  name: Atom,
+  arguments: Vec<TypeDefinition>,
+  span: Span,
+}
+
+// Name<Argument, ...>, `type_definition` being this rule
+pub fn type_definition<'a>(
+  type_definition: &TokenMatcher<'a, TypeDefinition>,
+) -> TokenMatcher<'a, TypeDefinition> {
+  // TODO unions, arrays, literals...
+  sequence!(
+    identifier(),
+    sequence!(
+      token!(LessThan),
+      comma_separated(type_definition.clone()),
+      token!(GreaterThan),
+    )
+    .optional(),
+  )
+  .map_with_span(|(name, arguments), span| TypeDefinition {
+    name,
+    arguments: arguments
+      .map(|(_, arguments, _)| arguments)
+      .unwrap_or_default(),
+    span,
+  })
+}
+
+impl TypeDefinition {
+  pub fn span(&self) -> Span {
+    self.span
+  }
+}
+
+/// As written: `Map<string, Array<T>>`
+impl fmt::Display for TypeDefinition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name)?;
+    if let Some((first, rest)) = self.arguments.split_first() {
+      write!(f, "<{}", first)?;
+      for argument in rest {
+        write!(f, ", {}", argument)?;
+      }
+      write!(f, ">")?;
+    }
+    Ok(())
+  }
+}
@@ -1,30 +1,164 @@
-use crate::lexer::Lexer;
-use crate::tokens::Token;
-use core::iter::Peekable;
+use crate::atom::Atom;
+use crate::cursor::TokenCursor;
+use crate::parser::{MatcherResult, TypedMatcher};
+use crate::sequence;
+use crate::span::{Span, Spanned};
+use crate::tokens::{Token, TokenKind};
+use std::ops::Deref;
+
+/// What the matchers read: a token and where it is, without the rest of `LocatedToken`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken<'a> {
+  pub token: Token<'a>,
+  pub span: Span,
+  pub preceded_by_newline: bool, // For the restricted productions, see `asi`
+}
+
+impl<'a> Deref for SpannedToken<'a> {
+  type Target = Token<'a>;
+
+  fn deref(&self) -> &Token<'a> {
+    &self.token
+  }
+}
+
+impl Spanned for SpannedToken<'_> {
+  fn span(&self) -> Span {
+    self.span
+  }
+}
+
+/// Builds an `Out` from tokens
+pub type TokenMatcher<'a, Out> = TypedMatcher<'a, SpannedToken<'a>, Out>;
+
+pub fn terminal<'a>(match_fn: fn(&SpannedToken<'a>) -> bool) -> TokenMatcher<'a, SpannedToken<'a>> {
+  TypedMatcher::terminal(match_fn)
+}
+
+/// A token of a kind, labeled for errors: token!(Comma)
+macro_rules! token {
+  ($kind:ident) => {
+    $crate::ast::utils::terminal(|token| token.kind == $crate::tokens::TokenKind::$kind).label(
+      &$crate::ast::utils::kind_label($crate::tokens::TokenKind::$kind),
+    )
+  };
+}
+pub(crate) use token;
+
+/// `,` or `import` as written, the others by what they are: identifier, string literal...
+pub fn kind_label(kind: TokenKind) -> String {
+  if kind.is_punctuator()
+    || kind.is_reserved_word()
+    || kind.is_strict_mode_reserved_word()
+    || kind.is_contextual_keyword()
+  {
+    format!("`{}`", kind.as_str())
+  } else {
+    kind.as_str().to_owned()
+  }
+}
+
+fn token_label(token: &Token) -> String {
+  match token.name() {
+    Some(name) => format!("`{}`", name),
+    None => kind_label(token.kind),
+  }
+}
+
+/// An identifier or a contextual keyword, its name
+pub fn identifier<'a>() -> TokenMatcher<'a, Atom> {
+  terminal(|token| token.kind.is_identifier())
+    .map(|token| token.name().unwrap())
+    .label("identifier")
+}
+
+/// item, item, ...
+pub fn comma_separated<'a, Out: 'a>(item: TokenMatcher<'a, Out>) -> TokenMatcher<'a, Vec<Out>> {
+  let tail = sequence!(token!(Comma), item.clone());
+  sequence!(item, tail.map(|(_, item)| item).repeat().optional()).map(|(head, tail)| {
+    std::iter::once(head)
+      .chain(tail.into_iter().flatten())
+      .collect()
+  })
+}
 
 /// Utils
-pub fn peek_token(lexer: &mut Peekable<Lexer>) -> Result<(Token, i32, i32), String> {
+/// Error tokens are skipped, the lexer keeps their diagnostics: a stray char doesn't stop the parser.
+pub fn peek_token<'a>(lexer: &mut TokenCursor<'a>) -> Result<(SpannedToken<'a>, i32, i32), String> {
+  while matches!(lexer.peek(), Some(located_token) if located_token.token.kind == TokenKind::Error)
+  {
+    lexer.next();
+  }
+
   match lexer.peek() {
-    Some(located_token) => match &located_token.token {
-      Ok(t) => Ok((t.clone(), located_token.line, located_token.col)),
-      Err(t) => Err(format!(
-        "line: {} col: {} ImportDeclaration: {}",
-        located_token.line, located_token.col, t
-      )),
-    },
+    Some(located_token) => Ok((
+      SpannedToken {
+        token: located_token.token.clone(),
+        span: located_token.span,
+        preceded_by_newline: located_token.preceded_by_newline,
+      },
+      located_token.line,
+      located_token.col,
+    )),
     _ => Err("Unexpected EOF".to_owned()),
   }
 }
 
-pub fn read_token(lexer: &mut Peekable<Lexer>) -> Result<(Token, i32, i32), String> {
-  match lexer.next() {
-    Some(located_token) => match &located_token.token {
-      Ok(t) => Ok((t.clone(), located_token.line, located_token.col)),
-      Err(t) => Err(format!(
-        "line: {} col: {} ImportDeclaration: {}",
-        located_token.line, located_token.col, t
-      )),
-    },
-    _ => Err("Unexpected EOF".to_owned()),
+/**
+ * Feeds the next token to the parser and consumes it if it's accepted.
+ * A symbol starting with `>` (`>>`, `>=`) is read again as `>` alone where type arguments
+ * are waiting on their closing `>`, or where the parser rejects it: `Array<Array<T>>`
+ */
+pub fn feed_token<'a, Out: 'a>(
+  parser: &mut TokenMatcher<'a, Out>,
+  lexer: &mut TokenCursor<'a>,
+) -> Result<MatcherResult<SpannedToken<'a>>, String> {
+  let (token, line, col) = peek_token(lexer)?;
+  let expected = parser.expected();
+  let closes_type = || {
+    let greater_than = kind_label(TokenKind::GreaterThan);
+    expected.labels.iter().any(|label| **label == *greater_than)
+  };
+
+  let result = if token.split_greater().is_some() && closes_type() {
+    lexer.rescan_as_greater_than();
+    parser.next(&peek_token(lexer)?.0)
+  } else if token.split_greater().is_some() {
+    let mut attempt = parser.clone();
+    match attempt.next(&token) {
+      MatcherResult::Rejected => {
+        lexer.rescan_as_greater_than();
+        parser.next(&peek_token(lexer)?.0)
+      }
+      result => {
+        *parser = attempt;
+        result
+      }
+    }
+  } else {
+    parser.next(&token)
+  };
+
+  if matches!(result, MatcherResult::Rejected) {
+    if expected.labels.is_empty() {
+      return Err(format!(
+        "line: {} col: {} unexpected token {:?}",
+        line, col, token
+      ));
+    }
+    let after = match &expected.after {
+      Some(after) => format!(" after {}", after),
+      None => String::new(),
+    };
+    return Err(format!(
+      "line: {} col: {} expected {}{}, found {}",
+      line,
+      col,
+      expected.one_of(),
+      after,
+      token_label(&token)
+    ));
   }
+  lexer.next();
+  Ok(result)
 }
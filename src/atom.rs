@@ -0,0 +1,114 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+/**
+ * Interned string, for identifiers and module specifiers.
+ * The table is global: the lexer, the AST and the checker share it, so the same text always
+ * gets the same atom and comparing two atoms is a pointer compare.
+ * Interned strings are never freed, there is one copy of every distinct name for the whole process.
+ */
+#[derive(Clone, Copy)]
+pub struct Atom(&'static str);
+
+fn interner() -> &'static Mutex<HashSet<&'static str>> {
+  static INTERNER: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+  INTERNER.get_or_init(Default::default)
+}
+
+thread_local! {
+  // Atoms this thread already got, so the global table is only locked for new names
+  static SEEN: RefCell<HashMap<&'static str, Atom, BuildHasherDefault<NameHasher>>> =
+    RefCell::new(HashMap::default());
+}
+
+// Names are short and not chosen by an attacker, a multiply per byte hashes them well enough
+#[derive(Default)]
+struct NameHasher(u64);
+
+impl Hasher for NameHasher {
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 = (self.0.rotate_left(5) ^ *byte as u64).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+  }
+
+  fn finish(&self) -> u64 {
+    self.0
+  }
+}
+
+impl Atom {
+  pub fn new(text: &str) -> Self {
+    if let Some(atom) = SEEN.with(|seen| seen.borrow().get(text).copied()) {
+      return atom;
+    }
+
+    let atom = {
+      let mut interner = interner().lock().unwrap();
+      match interner.get(text) {
+        Some(&text) => Atom(text),
+        None => {
+          let text: &'static str = Box::leak(text.into());
+          interner.insert(text);
+          Atom(text)
+        }
+      }
+    };
+    SEEN.with(|seen| seen.borrow_mut().insert(atom.0, atom));
+    atom
+  }
+
+  pub fn as_str(self) -> &'static str {
+    self.0
+  }
+}
+
+// There is one copy of every text, the address is enough
+impl PartialEq for Atom {
+  fn eq(&self, other: &Self) -> bool {
+    ptr::eq(self.0, other.0)
+  }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    ptr::hash(self.0, state)
+  }
+}
+
+impl From<&str> for Atom {
+  fn from(text: &str) -> Self {
+    Atom::new(text)
+  }
+}
+
+impl PartialEq<str> for Atom {
+  fn eq(&self, other: &str) -> bool {
+    self.as_str() == other
+  }
+}
+
+impl PartialEq<&str> for Atom {
+  fn eq(&self, other: &&str) -> bool {
+    self.as_str() == *other
+  }
+}
+
+// Printed as the text it stands for, the index means nothing outside of this process
+impl fmt::Debug for Atom {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(self.as_str(), f)
+  }
+}
+
+impl fmt::Display for Atom {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
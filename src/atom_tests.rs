@@ -0,0 +1,60 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod atom_tests {
+  use crate::atom::Atom;
+  use crate::lexer::Lexer;
+  use std::thread;
+
+  #[test]
+  fn same_text_same_atom() {
+    assert_eq!(Atom::new("useState"), Atom::new("useState"));
+    assert_ne!(Atom::new("useState"), Atom::new("useEffect"));
+    assert_eq!(Atom::new("useState").as_str(), "useState");
+  }
+
+  #[test]
+  fn atoms_are_shared_between_threads() {
+    let atom = Atom::new("sharedName");
+    let other = thread::spawn(|| Atom::new("sharedName")).join().unwrap();
+
+    assert_eq!(atom, other);
+  }
+
+  #[test]
+  fn threads_interning_at_once_agree() {
+    let names: Vec<String> = (0..200).map(|i| format!("name{}", i)).collect();
+    let handles: Vec<_> = (0..4)
+      .map(|_| {
+        let names = names.clone();
+        thread::spawn(move || names.iter().map(|n| Atom::new(n)).collect::<Vec<_>>())
+      })
+      .collect();
+    let atoms: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    for other in &atoms[1..] {
+      assert_eq!(&atoms[0], other);
+    }
+    assert_eq!(atoms[0][7], Atom::new("name7"));
+    assert_eq!(atoms[0][7].as_str(), "name7");
+  }
+
+  #[test]
+  fn lexer_interns_identifiers() {
+    let names: Vec<_> = Lexer::from_text("a b a \\u0061")
+      .map(|t| t.token.name().unwrap())
+      .collect();
+
+    assert_eq!(names[0], names[2]);
+    assert_eq!(names[0], names[3]);
+    assert_ne!(names[0], names[1]);
+    assert_eq!(names[0], Atom::new("a"));
+  }
+
+  #[test]
+  fn prints_the_text() {
+    let atom = Atom::new("printed");
+
+    assert_eq!(format!("{}", atom), "printed");
+    assert_eq!(format!("{:?}", atom), "\"printed\"");
+  }
+}
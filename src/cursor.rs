@@ -0,0 +1,85 @@
+use crate::lexer::{Checkpoint, Lexer, LocatedToken, Rescan};
+
+/**
+ * Reads the tokens of a lexer one at a time with a token of lookahead, like `Peekable`,
+ * but the parser can go back to a checkpoint or ask for the next token to be read again
+ * in its context with the `rescan_as_*` methods.
+ */
+pub struct TokenCursor<'a> {
+  lexer: Lexer<'a>,
+  current: Option<LocatedToken<'a>>,
+  before_current: Checkpoint, // To read `current` again
+}
+
+impl<'a> TokenCursor<'a> {
+  pub fn new(mut lexer: Lexer<'a>) -> Self {
+    let before_current = lexer.checkpoint();
+    let current = lexer.next();
+    TokenCursor {
+      lexer,
+      current,
+      before_current,
+    }
+  }
+
+  /// The next token, without consuming it
+  pub fn peek(&self) -> Option<&LocatedToken<'a>> {
+    self.current.as_ref()
+  }
+
+  pub fn lexer(&self) -> &Lexer<'a> {
+    &self.lexer
+  }
+
+  /// Where the next token starts
+  pub fn checkpoint(&self) -> Checkpoint {
+    self.before_current.clone()
+  }
+
+  /// Goes back to a checkpoint of this cursor, the tokens after it will be read again.
+  pub fn rewind(&mut self, checkpoint: &Checkpoint) {
+    self.lexer.rewind(checkpoint);
+    self.before_current = checkpoint.clone();
+    self.current = self.lexer.next();
+  }
+
+  /// `>>`, `>=`, `>>=`... is read as `>` alone, the end of `Array<Array<T>>`.
+  pub fn rescan_as_greater_than(&mut self) {
+    self.rescan(Rescan::GreaterThan)
+  }
+
+  /// `/` or `/=` starts a regex literal, where the lexer read a division.
+  pub fn rescan_as_regex(&mut self) {
+    self.rescan(Rescan::Regex)
+  }
+
+  /// `}` ends a template substitution: `}text${` or `}text\``.
+  pub fn rescan_as_template_continuation(&mut self) {
+    self.rescan(Rescan::TemplateContinuation)
+  }
+
+  /// `<` is a comparison or starts type parameters, not JSX.
+  pub fn rescan_as_less_than(&mut self) {
+    self.rescan(Rescan::LessThan)
+  }
+
+  /// `<` starts a JSX element.
+  pub fn rescan_as_jsx_element(&mut self) {
+    self.rescan(Rescan::JsxElement)
+  }
+
+  fn rescan(&mut self, rescan: Rescan) {
+    self.lexer.rewind(&self.before_current);
+    self.current = self.lexer.next_as(rescan);
+  }
+}
+
+impl<'a> Iterator for TokenCursor<'a> {
+  type Item = LocatedToken<'a>;
+
+  fn next(&mut self) -> Option<LocatedToken<'a>> {
+    self.current.as_ref()?;
+    self.before_current = self.lexer.checkpoint();
+    std::mem::replace(&mut self.current, self.lexer.next())
+  }
+}
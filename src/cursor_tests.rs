@@ -0,0 +1,151 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod cursor_tests {
+  use crate::atom::Atom;
+  use crate::cursor::TokenCursor;
+  use crate::lexer::Lexer;
+  use crate::tokens::{Literal, TemplateChunk, Token, TokenKind};
+  use std::borrow::Cow;
+
+  #[test]
+  fn peeks_and_reads() {
+    let mut cursor = TokenCursor::new(Lexer::from_text("a b"));
+
+    assert_eq!(peek(&cursor), Some(identifier("a")));
+    assert_eq!(cursor.next().map(|t| Ok(t.token)), Some(identifier("a")));
+    assert_eq!(peek(&cursor), Some(identifier("b")));
+    cursor.next();
+    assert_eq!(peek(&cursor), None);
+    assert!(cursor.next().is_none());
+  }
+
+  #[test]
+  fn rewinds_to_checkpoint() {
+    let mut cursor = TokenCursor::new(Lexer::from_text("a\n<b>text</b> c"));
+    cursor.next();
+    let checkpoint = cursor.checkpoint();
+    let first_pass: Vec<_> = cursor.by_ref().map(|t| (t.token, t.line, t.col)).collect();
+
+    cursor.rewind(&checkpoint);
+    let second_pass: Vec<_> = cursor.by_ref().map(|t| (t.token, t.line, t.col)).collect();
+
+    assert_eq!(first_pass.len(), 8);
+    assert_eq!(first_pass, second_pass);
+  }
+
+  #[test]
+  fn rescan_greater_than() {
+    let mut cursor = TokenCursor::new(Lexer::from_text("Array<Array<T>>= x"));
+    cursor.by_ref().take(5).count();
+    assert_eq!(peek(&cursor), Some(symbol(">>=")));
+
+    cursor.rescan_as_greater_than();
+    assert_eq!(peek(&cursor), Some(symbol(">")));
+    assert_eq!(cursor.next().unwrap().span.end, 14);
+    assert_eq!(peek(&cursor), Some(symbol(">=")));
+    assert_eq!(cursor.peek().unwrap().col, 15);
+  }
+
+  #[test]
+  fn rescan_regex() {
+    // `/` after `)` is a division, unless the parenthesis ends an `if` condition
+    let mut cursor = TokenCursor::new(Lexer::from_text("if (a) /=b/g.test(c)"));
+    cursor.by_ref().take(4).count();
+    assert_eq!(peek(&cursor), Some(symbol("/=")));
+
+    cursor.rescan_as_regex();
+    assert_eq!(
+      peek(&cursor),
+      Some(Ok(Token::literal(Literal::Regex("=b", "g"))))
+    );
+    cursor.next();
+    assert_eq!(peek(&cursor), Some(symbol(".")));
+  }
+
+  #[test]
+  fn rescan_template_continuation() {
+    // The parser recovers from the unterminated object: the `}` is the end of the substitution
+    let mut cursor = TokenCursor::new(Lexer::from_text("`a${ {b }c`;"));
+    cursor.by_ref().take(3).count();
+    assert_eq!(peek(&cursor), Some(symbol("}")));
+
+    cursor.rescan_as_template_continuation();
+    assert_eq!(
+      peek(&cursor),
+      Some(Ok(Token::template(
+        TokenKind::TemplateTail,
+        TemplateChunk {
+          cooked: Some(Cow::from("c")),
+          raw: Cow::from("c"),
+        }
+      )))
+    );
+    cursor.next();
+    assert_eq!(peek(&cursor), Some(symbol(";")));
+  }
+
+  #[test]
+  fn rescan_less_than() {
+    let mut cursor = TokenCursor::new(Lexer::from_text("type F = <T>(a: T) => T"));
+    cursor.by_ref().take(3).count();
+
+    cursor.rescan_as_less_than();
+    let tokens: Vec<_> = cursor.map(|t| t.token.kind).collect();
+    assert_eq!(
+      tokens,
+      vec![
+        TokenKind::LessThan,
+        TokenKind::Identifier,
+        TokenKind::GreaterThan,
+        TokenKind::OpenParen,
+        TokenKind::Identifier,
+        TokenKind::Colon,
+        TokenKind::Identifier,
+        TokenKind::CloseParen,
+        TokenKind::EqualsGreaterThan,
+        TokenKind::Identifier,
+      ]
+    );
+
+    let mut cursor = TokenCursor::new(Lexer::from_text("{}\n<a>text</a>"));
+    cursor.by_ref().take(2).count();
+
+    cursor.rescan_as_jsx_element();
+    let tokens: Vec<_> = cursor.map(|t| t.token.kind).collect();
+    assert_eq!(
+      tokens,
+      vec![
+        TokenKind::LessThan,
+        TokenKind::Identifier,
+        TokenKind::GreaterThan,
+        TokenKind::JsxText,
+        TokenKind::LessThanSlash,
+        TokenKind::Identifier,
+        TokenKind::GreaterThan,
+      ]
+    );
+  }
+
+  #[test]
+  fn rescan_keeps_unrelated_tokens() {
+    let mut cursor = TokenCursor::new(Lexer::from_text("a + b"));
+    cursor.next();
+    cursor.rescan_as_greater_than();
+
+    assert_eq!(peek(&cursor), Some(symbol("+")));
+  }
+
+  type TokenResult<'a> = Result<Token<'a>, String>;
+
+  fn peek<'a>(cursor: &TokenCursor<'a>) -> Option<TokenResult<'a>> {
+    cursor
+      .peek()
+      .map(|t| t.result().cloned().map_err(|d| d.message.clone()))
+  }
+  fn identifier(name: &str) -> TokenResult<'_> {
+    Ok(Token::identifier(Atom::new(name)))
+  }
+  fn symbol(text: &str) -> TokenResult<'_> {
+    Ok(Token::new(TokenKind::punctuator(text).unwrap()))
+  }
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod expression_tests {
+  use crate::ast::expression::{expression_grammar, Expression, ExpressionGrammar};
+  use crate::cursor::TokenCursor;
+  use crate::lexer::Lexer;
+
+  thread_local! {
+    // One grammar for all the tests that run on the thread
+    static GRAMMAR: ExpressionGrammar<'static> = expression_grammar();
+  }
+
+  fn parse(text: &'static str) -> String {
+    let mut cursor = TokenCursor::new(Lexer::from_text(text));
+    match GRAMMAR.with(|grammar| Expression::create(grammar, &mut cursor)) {
+      Some(Ok(expression)) => expression.to_string(),
+      Some(Err(error)) => error,
+      None => String::from("none"),
+    }
+  }
+
+  #[test]
+  fn precedence() {
+    assert_eq!(parse("a + b * c"), "(a + (b * c))");
+    assert_eq!(parse("a * b + c"), "((a * b) + c)");
+    assert_eq!(
+      parse("a || b && c | d ^ e & f"),
+      "(a || (b && (c | (d ^ (e & f)))))"
+    );
+    assert_eq!(parse("a == b < c << d + e"), "(a == (b < (c << (d + e))))");
+    assert_eq!(parse("a ?? b"), "(a ?? b)");
+    assert_eq!(parse("x = a > b ? c : d"), "(x = ((a > b) ? c : d))");
+  }
+
+  #[test]
+  fn associativity() {
+    assert_eq!(parse("a - b - c"), "((a - b) - c)");
+    assert_eq!(parse("a ** b ** c"), "(a ** (b ** c))");
+    assert_eq!(parse("a = b += c"), "(a = (b += c))");
+    assert_eq!(parse("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+    assert_eq!(parse("a ? b ? c : d : e"), "(a ? (b ? c : d) : e)");
+    assert_eq!(parse("a ? b : c = d"), "(a ? b : (c = d))");
+    assert_eq!(parse("a = b ? c : d = e"), "(a = (b ? c : (d = e)))");
+  }
+
+  #[test]
+  fn unary_and_update() {
+    assert_eq!(parse("-a * b"), "((-a) * b)");
+    assert_eq!(parse("!a && typeof b"), "((!a) && (typeof b))");
+    assert_eq!(parse("- -a"), "(-(-a))");
+    assert_eq!(parse("-a++"), "(-(a++))");
+    // Restricted production, the `++` after a line break is for `b`
+    assert_eq!(parse("a\n++b"), "a");
+    assert_eq!(parse("a\n++\nb"), "a");
+    assert_eq!(parse("a =\n++b"), "(a = (++b))");
+    assert_eq!(parse("++a.b"), "(++a.b)");
+    assert_eq!(parse("await a + b"), "((await a) + b)");
+    assert_eq!(parse("await + b"), "(await + b)");
+    assert_eq!(parse("++a ** b"), "((++a) ** b)");
+    assert_eq!(parse("a ** -b"), "(a ** (-b))");
+    assert_eq!(parse("(-a) ** b"), "((-a) ** b)");
+  }
+
+  #[test]
+  fn operations_that_need_parentheses() {
+    // What's read stops before the operator that can't take it
+    assert_eq!(parse("-a ** b"), "(-a)");
+    assert_eq!(parse("typeof a.b ** c"), "(typeof a.b)");
+    assert_eq!(parse("a ?? b || c"), "(a ?? b)");
+    assert_eq!(parse("a || b ?? c"), "(a || b)");
+    assert_eq!(parse("a ?? b && c"), "(a ?? b)");
+    assert_eq!(parse("a && b ?? c"), "(a && b)");
+    assert_eq!(parse("(a ?? b) || c"), "((a ?? b) || c)");
+    assert_eq!(parse("a ?? (b && c)"), "(a ?? (b && c))");
+    assert_eq!(parse("a ?? b | c"), "(a ?? (b | c))");
+  }
+
+  #[test]
+  fn calls_and_members() {
+    assert_eq!(parse("a.b.c(d, e + f)[g]"), "a.b.c(d, (e + f))[g]");
+    assert_eq!(parse("f()"), "f()");
+    assert_eq!(parse("a?.b?.(c)?.[d]"), "a?.b?.(c)?.[d]");
+    assert_eq!(parse("a.default.class"), "a.default.class");
+    assert_eq!(parse("-a.b!"), "(-a.b!)");
+    assert_eq!(parse("new_ + f(g(h))"), "(new_ + f(g(h)))");
+  }
+
+  #[test]
+  fn as_and_satisfies() {
+    assert_eq!(parse("a + b as T"), "((a + b) as T)");
+    assert_eq!(
+      parse("a as Array<T> satisfies U"),
+      "((a as Array<T>) satisfies U)"
+    );
+    assert_eq!(parse("a * b as T"), "((a * b) as T)");
+    assert_eq!(parse("a << b as T"), "((a << b) as T)");
+    // Same precedence as `<`, read left to right
+    assert_eq!(parse("a < b as T"), "((a < b) as T)");
+    assert_eq!(parse("a instanceof b as T"), "((a instanceof b) as T)");
+    assert_eq!(parse("a < b satisfies T"), "((a < b) satisfies T)");
+  }
+
+  #[test]
+  fn type_arguments_after_as_and_satisfies() {
+    // `<` after the type starts its arguments, never a comparison
+    assert_eq!(parse("x as Array<Array<T>>"), "(x as Array<Array<T>>)");
+    assert_eq!(parse("(x as Array<Array<T>>)"), "(x as Array<Array<T>>)");
+    assert_eq!(
+      parse("x satisfies Array<Array<T>>"),
+      "(x satisfies Array<Array<T>>)"
+    );
+    assert_eq!(parse("x as Array<T> + 1"), "((x as Array<T>) + 1)");
+    assert_eq!(
+      parse("x as Map<K, Array<T>> >> 1"),
+      "((x as Map<K, Array<T>>) >> 1)"
+    );
+    assert_eq!(parse("x as T < y"), "Unexpected EOF");
+  }
+
+  #[test]
+  fn literals_and_parentheses() {
+    assert_eq!(parse("(a + b) * c"), "((a + b) * c)");
+    assert_eq!(parse("1 + 2.5 * 'text'"), "(1 + (2.5 * \"text\"))");
+    assert_eq!(parse("10n + /a+/g.source"), "(10n + /a+/g.source)");
+    assert_eq!(
+      parse("this.a === null || true"),
+      "((this.a === null) || true)"
+    );
+  }
+
+  #[test]
+  fn stops_where_the_expression_ends() {
+    assert_eq!(parse("a + b)"), "(a + b)");
+    assert_eq!(parse("a b"), "a");
+    assert_eq!(parse(") a"), "none");
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(
+      parse("a + )"),
+      "line: 1 col: 5 expected expression, found `)`"
+    );
+    assert_eq!(
+      parse("a ? b )"),
+      "line: 1 col: 7 expected `:` after expression, found `)`"
+    );
+    assert_eq!(
+      parse("a?. )"),
+      "line: 1 col: 5 expected property name, `[` or `(`, found `)`"
+    );
+    assert_eq!(
+      parse("f(a b)"),
+      "line: 1 col: 5 expected `,` or `)` after expression, found `b`"
+    );
+  }
+
+  #[test]
+  fn spans() {
+    let text = "f(a) + -b.c";
+    let mut cursor = TokenCursor::new(Lexer::from_text(text));
+    let expression = Expression::create(&expression_grammar(), &mut cursor);
+    let printed = format!("{:?}", expression.unwrap().unwrap());
+
+    assert!(printed.ends_with("span: Span { start: 0, end: 11 } }"));
+    assert!(printed.contains("arguments: [Expression { kind: Identifier(\"a\"), span: Span { start: 2, end: 3 } }], optional: false }, span: Span { start: 0, end: 4 } }"));
+    assert!(
+      printed.contains("property: \"c\", optional: false }, span: Span { start: 8, end: 11 } }")
+    );
+    assert!(printed.contains("operator: Minus, operand: Expression"));
+  }
+}
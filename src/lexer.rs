@@ -1,98 +1,494 @@
+use crate::atom::Atom;
+use crate::entities::decode_entities;
+use crate::span::{LineIndex, Span, Spanned, TextEdit};
 use crate::tokens::*;
-use std::iter::Peekable;
-use std::vec::{IntoIter, Vec};
-// use std::{fs, io};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::io::Read;
+use std::vec::Vec;
+use std::{fs, io};
 
-pub struct Lexer {
-  raw_data: Peekable<IntoIter<char>>,
+/**
+ * Scans the source text in place with a byte cursor: token texts are slices of it,
+ * identifiers are interned as atoms, only literals with escapes are allocated.
+ */
+pub struct Lexer<'a> {
+  text: &'a str,
+  state: Vec<LexerState>,
+  line: i32,
+  col: i32,
+  offset: usize,                   // in bytes, where the next char starts
+  line_index: OnceCell<LineIndex>, // Only built if it's used
+  variant: LanguageVariant,
+  // `/` starts a regex or is a division depending on the previous token, same for `<` and JSX
+  regex_allowed: bool,
+  trivia: bool,      // Keep comments and whitespace attached to the tokens
+  line_break: bool,  // Since the last token, for automatic semicolon insertion
+  shebang_at: usize, // `#!` is only allowed at the start, after the BOM if there's one
+  end_of_file_trivia: Vec<Trivia<'a>>,
+  rescan: Option<Rescan>, // How to read the next token, only set by `next_as`
+  diagnostics: Vec<LexDiagnostic>,
+}
+
+/// Where the lexer was, to read again from there with `Lexer::rewind`
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
   state: Vec<LexerState>,
   line: i32,
   col: i32,
+  offset: usize,
+  regex_allowed: bool,
+  line_break: bool,
+  end_of_file_trivia: usize,
+  diagnostics: usize,
+}
+
+/**
+ * Tokens that only the parser can tell apart, see `Lexer::next_as`:
+ * GreaterThan => `>` alone, not `>>`, `>=`...: the end of `Array<Array<T>>`
+ * Regex => `/` or `/=` starts a regex literal
+ * TemplateContinuation => `}` ends a template substitution and the template goes on
+ * LessThan => `<` doesn't start JSX: `type F = <T>() => T`
+ * JsxElement => `<` starts a JSX element
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rescan {
+  GreaterThan,
+  Regex,
+  TemplateContinuation,
+  LessThan,
+  JsxElement,
+}
+
+/**
+ * The kind of source file, from its extension.
+ * JSX is only allowed in .tsx, .jsx and, like TypeScript does, .js files.
+ * Where it isn't, `<T>value` is a type assertion.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageVariant {
+  Ts,
+  Tsx,
+  Js,
+  Jsx,
+  Dts, // .d.ts
+}
+
+impl LanguageVariant {
+  pub fn from_path(path: &str) -> Option<Self> {
+    let has_extension = |extensions: &[&str]| extensions.iter().any(|e| path.ends_with(e));
+
+    if has_extension(&[".d.ts", ".d.mts", ".d.cts"]) {
+      Some(LanguageVariant::Dts)
+    } else if has_extension(&[".ts", ".mts", ".cts"]) {
+      Some(LanguageVariant::Ts)
+    } else if has_extension(&[".tsx"]) {
+      Some(LanguageVariant::Tsx)
+    } else if has_extension(&[".js", ".mjs", ".cjs"]) {
+      Some(LanguageVariant::Js)
+    } else if has_extension(&[".jsx"]) {
+      Some(LanguageVariant::Jsx)
+    } else {
+      None
+    }
+  }
+
+  pub fn allows_jsx(self) -> bool {
+    matches!(
+      self,
+      LanguageVariant::Tsx | LanguageVariant::Js | LanguageVariant::Jsx
+    )
+  }
 }
 
 #[derive(Copy, Clone, Debug)]
 enum LexerState {
   Typescript(TypescriptState),
   Jsx(JSXState),
+  Template, // `text ${ => Typescript state for the substitution is pushed on top
 }
 
 #[derive(Copy, Clone, Debug)]
 struct TypescriptState {
   bracket_stack: i32,
-  jsx_transition: JSXTransition,
-}
-
-/**
- * Case we've found `<identifier`. Only valid possibilities are:
- * if (a < identifier) => Typescript
- * const myFunction = <identifier extends any>() => Typescript
- * const myFunction = <identifier, T2>() => Typescript
- * <identifier> => JSX
- * <identifier /> => JSX
- * if (a < identifier / 3) => Typescript
- * <identifier foo=""> => JSX
- */
-#[derive(Copy, Clone, Debug)]
-enum JSXTransition {
-  None,       // '<' => Bracket, _ => None
-  Bracket,    // identifier => Identifier, _ => None
-  Identifier, // 'extends' => None, (>,/>,identifier) => JSX, _ => None
 }
 
 #[derive(Copy, Clone, Debug)]
 enum JSXState {
-  Element(i32), // <something<generic<T>> something="whatever"
-  Children,     // <something>...
-  Closing,      // </something
+  Element(i32),   // <something<generic<T>> something="whatever"
+  AttributeValue, // something= => "string", {expression}, <element /> or <>fragment</>
+  Children,       // <something>...
+  Closing,        // </something
 }
 
-impl Lexer {
-  pub fn from_text(text: &str) -> Self {
+impl<'a> Lexer<'a> {
+  pub fn from_text(text: &'a str) -> Self {
     Lexer {
-      raw_data: text.chars().collect::<Vec<_>>().into_iter().peekable(),
-      state: vec![LexerState::Typescript(TypescriptState {
-        bracket_stack: 1,
-        jsx_transition: JSXTransition::None,
-      })],
+      text,
+      state: vec![LexerState::Typescript(TypescriptState { bracket_stack: 1 })],
       line: 1,
       col: 1,
+      offset: 0,
+      line_index: OnceCell::new(),
+      variant: LanguageVariant::Tsx,
+      regex_allowed: true,
+      trivia: false,
+      line_break: false,
+      shebang_at: if text.starts_with(BOM) {
+        BOM.len_utf8()
+      } else {
+        0
+      },
+      end_of_file_trivia: Vec::new(),
+      rescan: None,
+      diagnostics: Vec::new(),
+    }
+  }
+
+  /// Source files must be UTF-8, otherwise the error points to the first invalid sequence.
+  pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, String> {
+    match std::str::from_utf8(bytes) {
+      Ok(text) => Ok(Self::from_text(text)),
+      Err(e) => Err(utf8_error(bytes, e)),
     }
   }
 
-  // pub fn from_file(file_path: &str) -> io::Result<Self> {
-  //   Ok(Self::from_text(&fs::read_to_string(file_path)?))
-  // }
+  /**
+   * Keeps comments, whitespace and newlines as trivia of the tokens instead of dropping them,
+   * so the source can be rebuilt from the tokens.
+   * A token owns the trivia on its line after it, up to and including the newline (trailing)
+   * and everything else before it (leading).
+   */
+  pub fn with_trivia(mut self) -> Self {
+    self.trivia = true;
+    self
+  }
+
+  /// Tsx by default
+  pub fn with_variant(mut self, variant: LanguageVariant) -> Self {
+    self.variant = variant;
+    self
+  }
+
+  /**
+   * The parser tells what a `<` is when the lexer guessed wrong, right after reading it and
+   * before reading the next token.
+   * The lexer starts JSX on `<` followed by a name where an expression can start, but in types
+   * `type F = <T>(a: T) => T` it's a type parameter list: lexing goes on in Typescript.
+   */
+  pub fn rescan_as_less_than(&mut self) {
+    if matches!(
+      self.state.last(),
+      Some(LexerState::Jsx(JSXState::Element(1)))
+    ) {
+      self.state.pop();
+    }
+  }
+
+  /// The other way around, after a `}` ending a block only the parser knows `<` starts an element: `{}\n<a />`
+  pub fn rescan_as_jsx_element(&mut self) {
+    if self.variant.allows_jsx() && !matches!(self.state.last(), Some(LexerState::Jsx(_))) {
+      self.state.push(LexerState::Jsx(JSXState::Element(1)));
+    }
+  }
+
+  pub fn checkpoint(&self) -> Checkpoint {
+    Checkpoint {
+      state: self.state.clone(),
+      line: self.line,
+      col: self.col,
+      offset: self.offset,
+      regex_allowed: self.regex_allowed,
+      line_break: self.line_break,
+      end_of_file_trivia: self.end_of_file_trivia.len(),
+      diagnostics: self.diagnostics.len(),
+    }
+  }
+
+  /// Goes back to a checkpoint of this lexer, the tokens after it will be read again.
+  pub fn rewind(&mut self, checkpoint: &Checkpoint) {
+    self.state.clone_from(&checkpoint.state);
+    self.line = checkpoint.line;
+    self.col = checkpoint.col;
+    self.offset = checkpoint.offset;
+    self.regex_allowed = checkpoint.regex_allowed;
+    self.line_break = checkpoint.line_break;
+    self
+      .end_of_file_trivia
+      .truncate(checkpoint.end_of_file_trivia);
+    self.diagnostics.truncate(checkpoint.diagnostics);
+  }
+
+  /**
+   * Reads the next token the way the parser says, if it can be read that way,
+   * it's read as usual otherwise: `>=` can be read as `>` but `+` is still `+`.
+   * Usually after rewinding to the checkpoint before a token that was read without the context.
+   */
+  pub fn next_as(&mut self, rescan: Rescan) -> Option<LocatedToken<'a>> {
+    if rescan == Rescan::Regex {
+      self.regex_allowed = true;
+    }
+    self.rescan = Some(rescan);
+    let result = self.next();
+    self.rescan = None;
 
-  fn get_next_char_while<F>(&mut self, raw_token: &mut String, cond: F)
+    if matches!(&result, Some(located_token) if located_token.token.kind == TokenKind::LessThan) {
+      match rescan {
+        Rescan::LessThan => self.rescan_as_less_than(),
+        Rescan::JsxElement => self.rescan_as_jsx_element(),
+        _ => {}
+      }
+    }
+    result
+  }
+
+  /**
+   * Lexes the text after an edit, reusing the tokens of the text before it.
+   * Lexing starts again from the last top level token that can't be changed by the edit and
+   * stops as soon as a token after the edit is the same as before, in the same state:
+   * the rest of `previous` is moved by the edit and kept.
+   * The lexer must be set up like the one that read `previous` (variant, trivia), and tokens
+   * the parser read again (`next_as`) are read again as the lexer guesses them.
+   */
+  pub fn relex(mut self, previous: &[LocatedToken<'a>], edit: &TextEdit) -> Vec<LocatedToken<'a>> {
+    // The token before the first one touching the edit can own it as trailing trivia,
+    // a `<` looks ahead a few tokens to tell JSX from type parameters.
+    let first_changed = previous.partition_point(|t| t.span.end < edit.span.start);
+    let lookahead_start = first_changed.saturating_sub(JSX_LOOKAHEAD);
+    let limit = match previous[lookahead_start..first_changed]
+      .iter()
+      .position(|t| t.token.kind == TokenKind::LessThan)
+    {
+      Some(position) => lookahead_start + position + 1,
+      None => first_changed,
+    };
+    let restart = previous[..limit]
+      .iter()
+      .rposition(|t| t.top_level)
+      .unwrap_or(0);
+
+    let mut tokens = previous[..restart].to_vec();
+    let mut leading_trivia = Vec::new();
+    if restart > 0 {
+      let from = &previous[restart];
+      self.offset = from.span.start;
+      self.line = from.line;
+      self.col = from.col;
+      self.regex_allowed = regex_allowed_after(&previous[restart - 1].token);
+      self.line_break = from.preceded_by_newline;
+      leading_trivia = from.leading_trivia.clone();
+    }
+
+    let mut old = previous.partition_point(|t| t.span.start < edit.span.end);
+    // What `/` and `<` mean depends on it, the state before a token isn't enough
+    let mut regex_allowed = self.regex_allowed;
+    while let Some(mut token) = self.next() {
+      leading_trivia.append(&mut token.leading_trivia);
+      token.leading_trivia = std::mem::take(&mut leading_trivia);
+
+      if token.span.start >= edit.new_end() {
+        while old < previous.len() && edit.shift(previous[old].span.start) < token.span.start {
+          old += 1;
+        }
+        let same_regex_allowed = match old.checked_sub(1) {
+          Some(before) => regex_allowed_after(&previous[before].token) == regex_allowed,
+          None => regex_allowed,
+        };
+        let same_state_after = match previous.get(old + 1) {
+          Some(after) => after.top_level == self.at_top_level(),
+          None => self.at_top_level(),
+        };
+        if let Some(same) = previous.get(old).filter(|same| {
+          edit.shift(same.span.start) == token.span.start
+            && same.span.len() == token.span.len()
+            && same.top_level
+            && token.top_level
+            && same.token == token.token
+            && same_regex_allowed
+            && same_state_after
+        }) {
+          let lines = token.line - same.line;
+          let cols = token.col - same.col;
+          let resync_line = same.line;
+          tokens.push(token);
+          tokens.extend(previous[old + 1..].iter().map(|t| {
+            let mut t = t.clone();
+            if t.line == resync_line {
+              t.col += cols;
+            }
+            t.line += lines;
+            shift_token(&mut t, edit);
+            t
+          }));
+          return tokens;
+        }
+      }
+      tokens.push(token);
+      regex_allowed = self.regex_allowed;
+    }
+    tokens
+  }
+
+  /// JSX can only start where an expression does
+  fn jsx_allowed(&self) -> bool {
+    self.variant.allows_jsx() && self.regex_allowed
+  }
+
+  /// Problems found in the tokens read so far
+  pub fn diagnostics(&self) -> &[LexDiagnostic] {
+    &self.diagnostics
+  }
+
+  /// Trivia after the last token, only known once the lexer has reached the end of the text.
+  pub fn end_of_file_trivia(&self) -> &[Trivia<'a>] {
+    &self.end_of_file_trivia
+  }
+
+  /// Reads chars while `cond` holds, returning them as a slice of the source.
+  fn read_while<F>(&mut self, cond: F) -> &'a str
   where
     F: Fn(char) -> bool,
   {
+    let start = self.offset;
     loop {
-      match self.raw_data.peek() {
-        Some(c) if cond(*c) => {
-          raw_token.push(*c);
+      match self.peek() {
+        Some(c) if cond(c) => {
           self.next_char();
         }
         _ => break,
       }
     }
+    &self.text[start..self.offset]
+  }
+
+  /// Source text after the cursor
+  fn rest(&self) -> &'a str {
+    &self.text[self.offset..]
+  }
+
+  /// Source text from `start` up to the cursor
+  fn slice(&self, start: usize) -> &'a str {
+    &self.text[start..self.offset]
+  }
+
+  fn peek(&self) -> Option<char> {
+    match self.text.as_bytes().get(self.offset) {
+      Some(byte) if byte.is_ascii() => Some(*byte as char),
+      Some(_) => self.text[self.offset..].chars().next(),
+      None => None,
+    }
+  }
+
+  /// Char `n` positions after the next one, `peek_nth(0)` is `peek()`
+  fn peek_nth(&self, n: usize) -> Option<char> {
+    self.text[self.offset..].chars().nth(n)
   }
 
   fn next_char(&mut self) -> Option<char> {
-    let result = self.raw_data.next();
+    let result = self.peek();
 
-    match result {
-      Some(r) => {
-        if r == '\n' {
-          self.line = self.line + 1;
-          self.col = 1;
-        } else {
-          self.col = self.col + 1;
-        }
+    if let Some(r) = result {
+      self.offset += r.len_utf8();
+      // `\r\n` is counted once, on the `\n`
+      if r == '\n' || (r == '\r' && self.peek() != Some('\n')) {
+        self.line += 1;
+        self.col = 1;
+      } else {
+        self.col += 1;
       }
+    };
+    result
+  }
+
+  /// Line/column lookups for the spans of the tokens of this text
+  pub fn line_index(&self) -> &LineIndex {
+    self.line_index.get_or_init(|| LineIndex::new(self.text))
+  }
+
+  /**
+   * Reads whitespace, newlines and comments before the next token, or after the last one up
+   * to the end of its line if `trailing`.
+   * Whitespace inside templates and JSX text is part of the text and JSX doesn't have comments.
+   */
+  fn read_trivia(&mut self, trailing: bool) -> Vec<Trivia<'a>> {
+    let mut trivia = Vec::new();
+    // Only whitespace, `/` and `#` start trivia: most of the time there is none to read
+    match self.text.as_bytes().get(self.offset) {
+      Some(byte) if byte.is_ascii_graphic() && !matches!(byte, b'/' | b'#') => return trivia,
+      None => return trivia,
       _ => {}
+    }
+    let comments_allowed = match self.state.last() {
+      Some(LexerState::Template) | Some(LexerState::Jsx(JSXState::Children)) => return trivia,
+      Some(LexerState::Typescript(_)) => true,
+      _ => false,
     };
-    return result;
+
+    loop {
+      let start = self.offset;
+      let kind = match self.peek() {
+        Some(c) if is_line_terminator(c) => {
+          self.next_char();
+          if c == '\r' && self.peek() == Some('\n') {
+            self.next_char();
+          }
+          TriviaKind::Newline
+        }
+        Some(c) if is_whitespace(c) => {
+          self.read_while(|c| is_whitespace(c) && !is_line_terminator(c));
+          TriviaKind::Whitespace
+        }
+        Some('#') if start == self.shebang_at && self.peek_nth(1) == Some('!') => {
+          self.read_while(|c| !is_line_terminator(c));
+          TriviaKind::Shebang
+        }
+        Some('/') if comments_allowed => match self.peek_nth(1) {
+          Some('/') => {
+            self.read_while(|c| !is_line_terminator(c));
+            TriviaKind::LineComment
+          }
+          Some('*') => {
+            self.next_char();
+            self.next_char();
+            let mut prev = '_';
+            while let Some(c) = self.next_char() {
+              if prev == '*' && c == '/' {
+                break;
+              }
+              prev = c;
+            }
+            TriviaKind::BlockComment
+          }
+          _ => break,
+        },
+        _ => break,
+      };
+      let text = self.slice(start);
+
+      // A comment spanning several lines counts as a line break
+      let ends_line = kind == TriviaKind::Newline
+        || (kind == TriviaKind::BlockComment && text.contains(is_line_terminator));
+      self.line_break |= ends_line;
+      if self.trivia {
+        trivia.push(Trivia {
+          kind,
+          span: Span::new(start, self.offset),
+          text,
+        });
+      }
+      if trailing && ends_line {
+        break;
+      }
+    }
+    trivia
+  }
+
+  /// Nothing is open: no braces, template, or JSX
+  fn at_top_level(&self) -> bool {
+    matches!(
+      self.state[..],
+      [LexerState::Typescript(TypescriptState { bracket_stack: 1 })]
+    )
   }
 
   fn replace_state(&mut self, state: LexerState) {
@@ -101,58 +497,208 @@ impl Lexer {
   }
 }
 
-pub type TokenResult = std::result::Result<Token, String>;
+type TokenResult<'a> = std::result::Result<Token<'a>, LexError>;
 
-pub struct LocatedToken {
+/// What went wrong reading a token, it becomes a diagnostic once the span of the token is known
+struct LexError {
+  code: DiagnosticCode,
+  message: String,
+}
+
+impl LexError {
+  fn new(code: DiagnosticCode, message: String) -> Self {
+    LexError { code, message }
+  }
+}
+
+/**
+ * A token that couldn't be read. The lexer goes on after it: the text becomes an `Error` token
+ * with this diagnostic, also kept in `Lexer::diagnostics` to report all of them at once.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexDiagnostic {
+  pub code: DiagnosticCode,
+  pub span: Span,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticCode {
+  InvalidCharacter,
+  UnterminatedString,
+  UnterminatedTemplate,
+  UnterminatedRegex,
+  InvalidRegexFlag,
+  InvalidEscape,
+  InvalidNumber,
+  InvalidIdentifier,
+  InvalidJsx,
+  UnbalancedBrace,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocatedToken<'a> {
   pub line: i32,
   pub col: i32,
-  pub token: TokenResult,
+  pub span: Span,
+  pub token: Token<'a>,
+  pub diagnostic: Option<LexDiagnostic>, // Only for `TokenKind::Error`
+  pub preceded_by_newline: bool,
+  pub top_level: bool, // Read in the initial state, `Lexer::relex` can start again from here
+  pub leading_trivia: Vec<Trivia<'a>>, // Only with `Lexer::with_trivia`
+  pub trailing_trivia: Vec<Trivia<'a>>,
 }
 
-impl Iterator for Lexer {
-  type Item = LocatedToken;
+impl Spanned for LocatedToken<'_> {
+  fn span(&self) -> Span {
+    self.span
+  }
+}
 
-  fn next(&mut self) -> Option<LocatedToken> {
+impl<'a> LocatedToken<'a> {
+  /// The token, or why it couldn't be read
+  pub fn result(&self) -> Result<&Token<'a>, &LexDiagnostic> {
+    match &self.diagnostic {
+      Some(diagnostic) => Err(diagnostic),
+      None => Ok(&self.token),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+  Whitespace,
+  Newline, // \n, \r\n, \r, \u2028 or \u2029
+  LineComment,
+  BlockComment,
+  Shebang, // #!/usr/bin/env node
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'a> {
+  pub kind: TriviaKind,
+  pub span: Span,
+  pub text: &'a str,
+}
+
+/// Reads a source file, failing with `InvalidData` if it isn't UTF-8.
+pub fn read_source_file(file_path: &str) -> io::Result<String> {
+  read_source(fs::File::open(file_path)?)
+}
+
+/// Reads a whole source, from stdin for example.
+pub fn read_source<R: Read>(mut reader: R) -> io::Result<String> {
+  let mut bytes = Vec::new();
+  reader.read_to_end(&mut bytes)?;
+  String::from_utf8(bytes).map_err(|e| {
+    let message = utf8_error(e.as_bytes(), e.utf8_error());
+    io::Error::new(io::ErrorKind::InvalidData, message)
+  })
+}
+
+fn utf8_error(bytes: &[u8], error: std::str::Utf8Error) -> String {
+  let valid = std::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap();
+  let mut lexer = Lexer::from_text(valid);
+  while lexer.next_char().is_some() {}
+
+  let end = error.valid_up_to() + error.error_len().unwrap_or(1);
+  format!(
+    "line: {} col: {} Invalid UTF-8 sequence: {:?}",
+    lexer.line,
+    lexer.col,
+    &bytes[error.valid_up_to()..end]
+  )
+}
+
+impl<'a> Iterator for Lexer<'a> {
+  type Item = LocatedToken<'a>;
+
+  fn next(&mut self) -> Option<LocatedToken<'a>> {
+    let mut leading_trivia = Vec::new();
     loop {
-      loop {
-        match self.raw_data.peek() {
-          Some(c) if c.is_whitespace() => {
-            self.next_char();
-            continue;
-          }
-          _ => break,
-        }
-      }
+      leading_trivia.append(&mut self.read_trivia(false));
 
       let last = self.state.len() - 1;
       let line = self.line;
       let col = self.col;
+      let start = self.offset;
+      let preceded_by_newline = self.line_break;
+      let top_level = self.at_top_level();
 
       // println!("{:?}", self.state[last]);
       let maybe_result = match self.state[last] {
         LexerState::Typescript(n) => next_typescript(self, n),
         LexerState::Jsx(n) => next_jsx(self, n),
+        LexerState::Template => next_template(self),
         // v => panic!("No parser for state {:?}", v),
       };
       match maybe_result {
         None => {}
-        Some(r) => {
-          break match r {
-            None => None,
-            Some(t) => Some(LocatedToken {
-              line: line,
-              col: col,
-              token: t,
-            }),
-          }
+        Some(None) => {
+          self.end_of_file_trivia.append(&mut leading_trivia);
+          break None;
+        }
+        Some(Some(result)) => {
+          let span = Span::new(start, self.offset);
+          let (token, diagnostic) = match result {
+            Ok(token) => (token, None),
+            Err(error) => {
+              let diagnostic = LexDiagnostic {
+                code: error.code,
+                span,
+                message: error.message,
+              };
+              self.diagnostics.push(diagnostic.clone());
+              (Token::new(TokenKind::Error), Some(diagnostic))
+            }
+          };
+          self.regex_allowed = regex_allowed_after(&token);
+          self.line_break = false;
+          let trailing_trivia = if self.trivia {
+            self.read_trivia(true)
+          } else {
+            Vec::new()
+          };
+          break Some(LocatedToken {
+            line,
+            col,
+            span,
+            token,
+            diagnostic,
+            preceded_by_newline,
+            top_level,
+            leading_trivia,
+            trailing_trivia,
+          });
         }
       }
     }
   }
 }
 
-fn next_typescript(lexer: &mut Lexer, state: TypescriptState) -> Option<Option<TokenResult>> {
-  let token: TokenResult;
+/// Tokens after a `<` that `starts_jsx_element` can read: `<const T extends =`
+const JSX_LOOKAHEAD: usize = 4;
+
+fn shift_token(token: &mut LocatedToken, edit: &TextEdit) {
+  let shift = |span: &mut Span| *span = Span::new(edit.shift(span.start), edit.shift(span.end));
+  shift(&mut token.span);
+  if let Some(diagnostic) = &mut token.diagnostic {
+    shift(&mut diagnostic.span);
+  }
+  for trivia in token
+    .leading_trivia
+    .iter_mut()
+    .chain(&mut token.trailing_trivia)
+  {
+    shift(&mut trivia.span);
+  }
+}
+
+fn next_typescript<'a>(
+  lexer: &mut Lexer<'a>,
+  state: TypescriptState,
+) -> Option<Option<TokenResult<'a>>> {
+  let token: TokenResult<'a>;
 
   let first_char: char;
   loop {
@@ -166,311 +712,1082 @@ fn next_typescript(lexer: &mut Lexer, state: TypescriptState) -> Option<Option<T
     }
   }
 
-  if first_char.is_numeric() {
-    let mut value = first_char.to_string();
-    lexer.get_next_char_while(&mut value, |c| c.is_numeric());
+  let starts_number = first_char.is_ascii_digit()
+    || (first_char == '.' && matches!(lexer.peek(), Some(c) if c.is_ascii_digit()));
 
-    token = match value.parse() {
-      Ok(i) => Ok(Token::Literal(Literal::Integer(i))),
-      Err(_) => Err(format!("Integer literal {} is invalid", value)),
-    };
+  if starts_number {
+    token = read_number(lexer, first_char);
+  } else if is_identifier_start(first_char) || first_char == '\\' {
+    let identifier = read_identifier(lexer, first_char);
 
-    if !matches!(state.jsx_transition, JSXTransition::None) {
-      // not covered by tests, only for correctness (reset JSX state)
-      lexer.replace_state(LexerState::Typescript(TypescriptState {
-        bracket_stack: state.bracket_stack,
-        jsx_transition: JSXTransition::None,
-      }))
-    }
-  } else if is_identifier(first_char) {
-    let mut name = first_char.to_string();
-    lexer.get_next_char_while(&mut name, is_identifier);
-
-    match state.jsx_transition {
-      JSXTransition::Bracket => lexer.replace_state(LexerState::Typescript(TypescriptState {
-        bracket_stack: state.bracket_stack,
-        jsx_transition: JSXTransition::Identifier,
-      })),
-      JSXTransition::Identifier => {
-        // We must replace state before pushing
-        // because when we pop back, we want to reset to None
-        lexer.replace_state(LexerState::Typescript(TypescriptState {
-          bracket_stack: state.bracket_stack,
-          jsx_transition: JSXTransition::None,
-        }));
-        if name != "extends" {
-          lexer.state.push(LexerState::Jsx(JSXState::Element(1)))
-        }
-      }
-      _ => {}
-    }
-    if KNOWN_KEYWORDS.contains(&&name[..]) {
-      token = Ok(Token::Keyword(name))
-    } else {
-      token = Ok(Token::Identifier(name))
-    };
+    token = identifier.and_then(|(name, escaped)| match TokenKind::keyword(&name) {
+      Some(kind) if !escaped => Ok(Token::new(kind)),
+      // `\u0061s` can only be the identifier `as`, and `\u0069f` can't be `if`
+      Some(kind) if kind.is_contextual_keyword() => Ok(Token::identifier(Atom::new(&name))),
+      None => Ok(Token::identifier(Atom::new(&name))),
+      Some(_) => Err(LexError::new(
+        DiagnosticCode::InvalidIdentifier,
+        format!("Keywords cannot contain escape characters: {}", name),
+      )),
+    });
+  } else if first_char == '`' {
+    token = read_template(lexer, true);
   } else if first_char == '"' || first_char == '\'' {
-    let mut value = String::new();
-    lexer.get_next_char_while(&mut value, |c| c != first_char);
-    // We need to exclude the last closing character
-    lexer.next_char();
-    token = Ok(Token::Literal(Literal::Str(value)));
-
-    if !matches!(state.jsx_transition, JSXTransition::None) {
-      // not covered by tests, only for correctness (reset JSX state)
-      lexer.replace_state(LexerState::Typescript(TypescriptState {
-        bracket_stack: state.bracket_stack,
-        jsx_transition: JSXTransition::None,
-      }))
-    }
+    token = read_string(lexer, first_char);
+  } else if first_char == '/'
+    && lexer.regex_allowed
+    && !matches!(lexer.peek(), Some('/') | Some('*'))
+  {
+    token = read_regex(lexer);
   } else {
-    let raw = read_symbol(lexer, &first_char);
+    let symbol = if first_char == '>' && lexer.rescan == Some(Rescan::GreaterThan) {
+      Some(TokenKind::GreaterThan)
+    } else {
+      read_symbol(lexer, first_char)
+    };
+    let kind = match symbol {
+      Some(kind) => kind,
+      None => {
+        return Some(Some(Err(LexError::new(
+          DiagnosticCode::InvalidCharacter,
+          format!("Unknown token: {}", first_char),
+        ))))
+      }
+    };
 
     // Change state
-    if raw == "{" {
-      lexer.replace_state(LexerState::Typescript(TypescriptState {
+    match kind {
+      TokenKind::CloseBrace if lexer.rescan == Some(Rescan::TemplateContinuation) => {
+        // Whatever is still open in the substitution is dropped
+        let template = lexer
+          .state
+          .iter()
+          .rposition(|state| matches!(state, LexerState::Template));
+        if let Some(template) = template {
+          lexer.state.truncate(template + 1);
+          return next_template(lexer);
+        }
+      }
+      TokenKind::OpenBrace => lexer.replace_state(LexerState::Typescript(TypescriptState {
         bracket_stack: state.bracket_stack + 1,
-        jsx_transition: state.jsx_transition,
-      }))
-    } else if raw == "}" {
-      if state.bracket_stack == 1 {
-        lexer.state.pop();
-      } else {
-        lexer.replace_state(LexerState::Typescript(TypescriptState {
-          bracket_stack: state.bracket_stack - 1,
-          jsx_transition: state.jsx_transition,
-        }))
+      })),
+      TokenKind::CloseBrace if state.bracket_stack == 1 && lexer.state.len() == 1 => {
+        // Nothing to close, the file goes on at top level
+        return Some(Some(Err(LexError::new(
+          DiagnosticCode::UnbalancedBrace,
+          String::from("Unexpected }"),
+        ))));
       }
-    } else {
-      match state.jsx_transition {
-        JSXTransition::None if raw == "<" => {
-          lexer.replace_state(LexerState::Typescript(TypescriptState {
-            bracket_stack: state.bracket_stack,
-            jsx_transition: JSXTransition::Bracket,
-          }))
-        }
-        JSXTransition::None if raw == "<>" => lexer.state.push(LexerState::Jsx(JSXState::Children)),
-        JSXTransition::Identifier if raw == ">" => {
-          lexer.replace_state(LexerState::Typescript(TypescriptState {
-            bracket_stack: state.bracket_stack,
-            jsx_transition: JSXTransition::None,
-          }));
-          lexer.state.push(LexerState::Jsx(JSXState::Children))
-        }
-        JSXTransition::Identifier if raw == "<" => {
-          lexer.replace_state(LexerState::Typescript(TypescriptState {
-            bracket_stack: state.bracket_stack,
-            jsx_transition: JSXTransition::None,
-          }));
-          lexer.state.push(LexerState::Jsx(JSXState::Element(2)))
-        }
-        JSXTransition::Identifier if raw == "/>" => {
+      TokenKind::CloseBrace => {
+        if state.bracket_stack == 1 {
+          lexer.state.pop();
+          if matches!(lexer.state.last(), Some(LexerState::Template)) {
+            // This `}` closes a template substitution, the template continues from here.
+            return next_template(lexer);
+          }
+        } else {
           lexer.replace_state(LexerState::Typescript(TypescriptState {
-            bracket_stack: state.bracket_stack,
-            jsx_transition: JSXTransition::None,
+            bracket_stack: state.bracket_stack - 1,
           }))
         }
-        _ => {
-          if raw != "." {
-            lexer.replace_state(LexerState::Typescript(TypescriptState {
-              bracket_stack: state.bracket_stack,
-              jsx_transition: JSXTransition::None,
-            }))
-          }
-        }
-      };
-    }
-
-    token = match &raw[..] {
-      // Ignore comments until newline
-      s if s == "//" => {
-        lexer.get_next_char_while(&mut String::new(), |c| c != '\n');
-        return None;
       }
-      s if s == "/*" => {
-        let mut prev = '_';
-
-        loop {
-          match lexer.raw_data.peek() {
-            Some('/') if prev == '*' => {
-              lexer.next_char();
-              break;
-            }
-            Some(c) => {
-              prev = *c;
-              lexer.next_char();
-            }
-            _ => break,
-          }
-        }
-        return None;
+      TokenKind::LessThan if lexer.jsx_allowed() && starts_jsx_element(lexer.rest()) => {
+        lexer.state.push(LexerState::Jsx(JSXState::Element(1)))
       }
-      s if VALID_SYMBOLS.contains(&s) => Ok(Token::Symbol(raw)),
-      _ => Err(format!("Unknown token: {}", raw)),
-    };
+      TokenKind::LessThanGreaterThan if lexer.jsx_allowed() => {
+        lexer.state.push(LexerState::Jsx(JSXState::Children))
+      }
+      _ => {}
+    }
+
+    token = Ok(Token::new(kind));
   }
 
-  return Some(Some(token));
+  Some(Some(token))
 }
 
-fn next_jsx(lexer: &mut Lexer, state: JSXState) -> Option<Option<TokenResult>> {
-  let first_char: char;
-  loop {
-    match lexer.next_char() {
-      Some(c) if c.is_whitespace() => continue,
-      Some(c) => {
-        first_char = c;
-        break;
-      }
-      None => return Some(None),
+/**
+ * `text` follows a `<` where an expression starts, in a file with JSX.
+ * It's an element if a name follows, unless it's the type parameters of an arrow function.
+ * TypeScript looks ahead the same way: `<T,>`, `<T = U>` and `<T extends U>` are type parameters,
+ * `<T>`, `<T extends>` and `<T extends="">` are elements.
+ */
+fn starts_jsx_element(text: &str) -> bool {
+  fn skip_whitespace(text: &str) -> &str {
+    text.trim_start_matches(is_whitespace)
+  }
+  fn read_word(text: &str) -> (&str, &str) {
+    let text = skip_whitespace(text);
+    let end = text.find(|c| !is_identifier_part(c)).unwrap_or(text.len());
+    text.split_at(end)
+  }
+
+  let (mut name, mut rest) = read_word(text);
+  if name == "const" {
+    let (next, next_rest) = read_word(rest);
+    if !next.is_empty() {
+      name = next;
+      rest = next_rest;
     }
   }
+  if !name.starts_with(is_identifier_start) {
+    return false;
+  }
 
-  let token: TokenResult = match state {
+  let rest = skip_whitespace(rest);
+  if rest.starts_with(',') || rest.starts_with('=') {
+    return false;
+  }
+  match read_word(rest) {
+    ("extends", rest) => matches!(skip_whitespace(rest).chars().next(), Some('=' | '>' | '/')),
+    _ => true,
+  }
+}
+
+fn next_jsx<'a>(lexer: &mut Lexer<'a>, state: JSXState) -> Option<Option<TokenResult<'a>>> {
+  let first_char = match lexer.next_char() {
+    Some(c) => c,
+    None => return Some(None),
+  };
+
+  let token: TokenResult<'a> = match state {
     JSXState::Element(element_stack) => {
       /* Valid tokens are just a few:
-       * <element value="asdf" typescript={123}>
-       * - identifier
-       * - =
+       * <svg:element data-value="asdf" typescript={123} {...spread} boolean>
+       * - identifier, with `-` after the first char
+       * - : between a namespace and a name
+       * - = => attribute value
        * - " => string literal
        * - { => go typescript
        * - > => push children
        * - /> => pop state
        */
 
-      if is_identifier(first_char) {
-        let mut name = first_char.to_string();
-        lexer.get_next_char_while(&mut name, is_identifier);
-    
-        Ok(Token::Identifier(name))
+      if is_identifier_start(first_char) {
+        let name = read_name(lexer, first_char);
+
+        Ok(Token::identifier(Atom::new(name)))
+      } else if first_char.is_ascii_digit() {
+        // <div 1a="" /> isn't a number followed by an identifier
+        let name = read_name(lexer, first_char);
+
+        Err(LexError::new(
+          DiagnosticCode::InvalidJsx,
+          format!("JSX names can't start with a digit: {}", name),
+        ))
       } else if first_char == '"' || first_char == '\'' {
-        let mut value = String::new();
-        lexer.get_next_char_while(&mut value, |c| c != first_char);
-        lexer.next_char();
+        read_jsx_string(lexer, first_char)
+      } else if first_char == '=' && element_stack == 1 {
+        lexer.state.push(LexerState::Jsx(JSXState::AttributeValue));
 
-        Ok(Token::Literal(Literal::Str(value)))
+        Ok(Token::new(TokenKind::Equals))
       } else {
-        let symbol = read_symbol(lexer, &first_char);
+        // `>>` closes two elements/generics: <Elm<G>>
+        let symbol = if first_char == '>' {
+          Some(TokenKind::GreaterThan)
+        } else {
+          read_symbol(lexer, first_char)
+        };
 
         // <Component.Element<Pick<State, 'foo'> value="1" js={1} />
-        if symbol == "=" || symbol == "-" || symbol == "." || symbol == "," {
-          Ok(Token::Symbol(symbol))
-        } else if symbol == "{" {
-          lexer.state.push(LexerState::Typescript(TypescriptState {
-            bracket_stack: 1,
-            jsx_transition: JSXTransition::None,
-          }));
-  
-          Ok(Token::Symbol(symbol))
-        } else if symbol == ">" {
-          if element_stack == 1 {
-            lexer.replace_state(LexerState::Jsx(JSXState::Children));
-          } else {
-            lexer.replace_state(LexerState::Jsx(JSXState::Element(element_stack-1)));
+        match symbol {
+          Some(TokenKind::Equals)
+          | Some(TokenKind::Colon)
+          | Some(TokenKind::Dot)
+          | Some(TokenKind::Comma) => Ok(Token::new(symbol.unwrap())),
+          Some(TokenKind::OpenBrace) => {
+            lexer
+              .state
+              .push(LexerState::Typescript(TypescriptState { bracket_stack: 1 }));
+
+            Ok(Token::new(TokenKind::OpenBrace))
+          }
+          Some(TokenKind::GreaterThan) => {
+            if element_stack == 1 {
+              lexer.replace_state(LexerState::Jsx(JSXState::Children));
+            } else {
+              lexer.replace_state(LexerState::Jsx(JSXState::Element(element_stack - 1)));
+            }
+            Ok(Token::new(TokenKind::GreaterThan))
           }
-          Ok(Token::Symbol(symbol))
-        } else if symbol == "<" {
-          lexer.replace_state(LexerState::Jsx(JSXState::Element(element_stack+1)));
+          Some(TokenKind::LessThan) => {
+            lexer.replace_state(LexerState::Jsx(JSXState::Element(element_stack + 1)));
 
-          Ok(Token::Symbol(symbol))
-        } else if symbol == "/>" {
+            Ok(Token::new(TokenKind::LessThan))
+          }
+          Some(TokenKind::SlashGreaterThan) => {
+            lexer.state.pop();
+
+            Ok(Token::new(TokenKind::SlashGreaterThan))
+          }
+          Some(kind) => Err(LexError::new(
+            DiagnosticCode::InvalidCharacter,
+            format!("Unkown token {}", kind.as_str()),
+          )),
+          None => Err(LexError::new(
+            DiagnosticCode::InvalidCharacter,
+            format!("Unkown token {}", first_char),
+          )),
+        }
+      }
+    }
+    JSXState::AttributeValue => {
+      /* The value replaces this state, the element continues once it's read:
+       * - " => string literal
+       * - { => go typescript
+       * - < => element
+       * - <> => fragment
+       */
+
+      match first_char {
+        '"' | '\'' => {
           lexer.state.pop();
-  
-          Ok(Token::Symbol(symbol))
-        } else {
-          Err(format!("Unkown token {}", symbol))
+          read_jsx_string(lexer, first_char)
+        }
+        '{' => {
+          lexer.replace_state(LexerState::Typescript(TypescriptState { bracket_stack: 1 }));
+
+          Ok(Token::new(TokenKind::OpenBrace))
+        }
+        '<' if lexer.peek() == Some('>') => {
+          lexer.next_char();
+          lexer.replace_state(LexerState::Jsx(JSXState::Children));
+
+          Ok(Token::new(TokenKind::LessThanGreaterThan))
+        }
+        '<' => {
+          lexer.replace_state(LexerState::Jsx(JSXState::Element(1)));
+
+          Ok(Token::new(TokenKind::LessThan))
+        }
+        _ => {
+          // The attribute is missing its value, the element goes on from this char: `<a b=>`
+          lexer.state.pop();
+          lexer.offset -= first_char.len_utf8();
+          lexer.col -= 1;
+          Err(LexError::new(
+            DiagnosticCode::InvalidJsx,
+            format!("Expected a JSX attribute value, found {}", first_char),
+          ))
         }
       }
-    },
+    }
     JSXState::Children => {
       /* Valid tokens are just a few:
        * some long text {123} <element />
        * - { => go typescript
        * - < => go Element
-       * - anything else => string literal
+       * - anything else => JSX text, up to the next { or <
        */
 
       if first_char == '{' {
-        lexer.state.push(LexerState::Typescript(TypescriptState {
-          bracket_stack: 1,
-          jsx_transition: JSXTransition::None,
-        }));
+        lexer
+          .state
+          .push(LexerState::Typescript(TypescriptState { bracket_stack: 1 }));
 
-        Ok(Token::Symbol(String::from("{")))
+        Ok(Token::new(TokenKind::OpenBrace))
       } else if first_char == '<' {
-        let symbol = read_symbol(lexer, &first_char);
-
-        if symbol == "<" {
-          lexer.state.push(LexerState::Jsx(JSXState::Element(1)));
-  
-          Ok(Token::Symbol(String::from(symbol)))
-        } else if symbol == "</" {
-          lexer.replace_state(LexerState::Jsx(JSXState::Closing));
-  
-          Ok(Token::Symbol(String::from(symbol)))
-        } else if symbol == "<>" {
-          lexer.state.push(LexerState::Jsx(JSXState::Children));
-
-          Ok(Token::Symbol(String::from(symbol)))
-        } else if symbol == "</>" {
-          lexer.state.pop();
+        // `<<` would be read as a shift
+        let symbol = match lexer.peek() {
+          Some('<') => TokenKind::LessThan,
+          _ => read_symbol(lexer, first_char).unwrap(),
+        };
 
-          Ok(Token::Symbol(String::from(symbol)))
-        } else {
-          Err(format!("Unkown token {}", symbol))
+        match symbol {
+          TokenKind::LessThan => lexer.state.push(LexerState::Jsx(JSXState::Element(1))),
+          TokenKind::LessThanSlash => lexer.replace_state(LexerState::Jsx(JSXState::Closing)),
+          TokenKind::LessThanGreaterThan => lexer.state.push(LexerState::Jsx(JSXState::Children)),
+          TokenKind::LessThanSlashGreaterThan => {
+            lexer.state.pop();
+          }
+          _ => {
+            return Some(Some(Err(LexError::new(
+              DiagnosticCode::InvalidCharacter,
+              format!("Unkown token {}", symbol.as_str()),
+            ))))
+          }
         }
+
+        Ok(Token::new(symbol))
       } else {
-        let mut value = String::from(first_char);
-        lexer.get_next_char_while(&mut value, |c| c != '{' && c != '<');
+        let start = lexer.offset - first_char.len_utf8();
+        lexer.read_while(|c| c != '{' && c != '<');
+        let raw = lexer.slice(start);
 
-        Ok(Token::Literal(Literal::Str(value)))
+        Ok(Token::jsx_text(JsxText {
+          raw,
+          value: jsx_text_value(raw),
+        }))
       }
     }
     JSXState::Closing => {
       /* We're just expecting to close:
-       * </element.subelement>
+       * </element.subelement>, </svg:element>, </custom-element>
        */
 
-      if is_identifier(first_char) {
-        let mut name = first_char.to_string();
-        lexer.get_next_char_while(&mut name, is_identifier);
-    
-        Ok(Token::Identifier(name))
+      if is_identifier_start(first_char) {
+        let name = read_name(lexer, first_char);
+
+        Ok(Token::identifier(Atom::new(name)))
       } else if first_char == '.' {
-        Ok(Token::Symbol(String::from(".")))
+        Ok(Token::new(TokenKind::Dot))
+      } else if first_char == ':' {
+        Ok(Token::new(TokenKind::Colon))
       } else if first_char == '>' {
         lexer.state.pop();
-        Ok(Token::Symbol(String::from(">")))
+        Ok(Token::new(TokenKind::GreaterThan))
       } else {
-        panic!("Unknown token starting with {}", first_char)
+        Err(LexError::new(
+          DiagnosticCode::InvalidJsx,
+          format!(
+            "Unexpected {} in JSX closing element, expected a name or >",
+            first_char
+          ),
+        ))
       }
     }
   };
 
-  return Some(Some(token));
+  Some(Some(token))
 }
 
-fn read_symbol(lexer: &mut Lexer, first_char: &char) -> String {
-  let mut raw = first_char.to_string();
-  loop {
-    if let Some(peek) = lexer.raw_data.peek() {
-      raw.push(*peek);
-    } else {
-      // We reached the end of the program.
+/**
+ * Whitespace in JSX text, as React and TypeScript handle it:
+ * - every line is trimmed, except the start of the first one and the end of the last one
+ * - the lines left with text are joined with a space, the others are dropped
+ * - entities are decoded after trimming, so `&#32;` or `&nbsp;` are kept
+ *
+ * `<a>  one\n   two  </a>` => "  one two  "
+ */
+fn jsx_text_value(raw: &str) -> Option<Cow<'_, str>> {
+  let is_space = |c: char| is_whitespace(c) && !is_line_terminator(c);
+  let last = raw.split(is_line_terminator).count() - 1;
+  let mut lines = raw
+    .split(is_line_terminator)
+    .enumerate()
+    .map(|(i, line)| {
+      let line = if i == 0 {
+        line
+      } else {
+        line.trim_start_matches(is_space)
+      };
+      if i == last {
+        line
+      } else {
+        line.trim_end_matches(is_space)
+      }
+    })
+    .filter(|line| !line.is_empty());
+
+  let mut value = decode_entities(lines.next()?);
+  for line in lines {
+    let value = value.to_mut();
+    value.push(' ');
+    value.push_str(&decode_entities(line));
+  }
+  Some(value)
+}
+
+/**
+ * Reads a string literal after its opening quote, decoding escape sequences.
+ * A string can't contain a line break unless it's escaped (line continuation),
+ * in that case it's reported as unterminated and lexing resumes on the next line.
+ * The value borrows the raw text unless there are escapes to decode.
+ */
+fn read_string<'a>(lexer: &mut Lexer<'a>, quote: char) -> TokenResult<'a> {
+  let start = lexer.offset;
+  let mut value: Option<String> = None; // Only once there's an escape
+  let mut error: Option<LexError> = None;
+
+  let end = loop {
+    match lexer.peek() {
+      None | Some('\n') | Some('\r') => {
+        return Err(LexError::new(
+          DiagnosticCode::UnterminatedString,
+          String::from("Unterminated string literal"),
+        ));
+      }
+      Some(c) if c == quote => {
+        let end = lexer.offset;
+        lexer.next_char();
+        break end;
+      }
+      Some('\\') => {
+        let value = value.get_or_insert_with(|| lexer.slice(start).to_string());
+        lexer.next_char();
+        match read_escape(lexer, false) {
+          Ok(Some(c)) => value.push(c),
+          Ok(None) => {}
+          Err(e) => {
+            error.get_or_insert(e);
+          }
+        }
+      }
+      Some(c) => {
+        lexer.next_char();
+        if let Some(value) = &mut value {
+          value.push(c);
+        }
+      }
+    }
+  };
+
+  let raw = &lexer.text[start..end];
+  match error {
+    Some(e) => Err(e),
+    None => Ok(Token::literal(Literal::Str(
+      value.map_or(Cow::Borrowed(raw), Cow::Owned),
+      raw,
+    ))),
+  }
+}
+
+/**
+ * A `/` can only start a regex where an expression is expected:
+ * a = /re/ (/re/) return /re/ => Regex
+ * a / b (a) / b a[0] / b => Division
+ * `}` is ambiguous (block vs object literal), we go with division.
+ */
+fn regex_allowed_after(token: &Token) -> bool {
+  // Contextual keywords are identifiers here: `number / 2`, `type / 1`,
+  // except those that are never an operand before a `/`: `await /re/`, `for (a of /re/)`
+  let is_operand = token.kind.is_identifier()
+    && !matches!(
+      token.kind,
+      TokenKind::Await
+        | TokenKind::Of
+        | TokenKind::As
+        | TokenKind::Satisfies
+        | TokenKind::Keyof
+        | TokenKind::Infer
+    );
+  !is_operand
+    && !matches!(
+      token.kind,
+      TokenKind::NumericLiteral
+        | TokenKind::BigIntLiteral
+        | TokenKind::StringLiteral
+        | TokenKind::RegexLiteral
+        | TokenKind::This
+        | TokenKind::Super
+        | TokenKind::True
+        | TokenKind::False
+        | TokenKind::Null
+        | TokenKind::NoSubstitutionTemplate
+        | TokenKind::TemplateTail
+        | TokenKind::CloseParen
+        | TokenKind::CloseBracket
+        | TokenKind::CloseBrace
+        | TokenKind::PlusPlus
+        | TokenKind::MinusMinus
+    )
+}
+
+/**
+ * Reads a regex literal after its opening `/`: /pattern/flags
+ * A `/` inside a class (`[/]`) or escaped doesn't close the pattern.
+ */
+fn read_regex<'a>(lexer: &mut Lexer<'a>) -> TokenResult<'a> {
+  let start = lexer.offset;
+  let mut in_class = false;
+
+  let pattern = loop {
+    match lexer.peek() {
+      None | Some('\n') | Some('\r') | Some('\u{2028}') | Some('\u{2029}') => {
+        return Err(LexError::new(
+          DiagnosticCode::UnterminatedRegex,
+          String::from("Unterminated regular expression literal"),
+        ));
+      }
+      Some('/') if !in_class => {
+        let pattern = lexer.slice(start);
+        lexer.next_char();
+        break pattern;
+      }
+      Some('\\') => {
+        lexer.next_char();
+        match lexer.peek() {
+          None | Some('\n') | Some('\r') => {}
+          Some(_) => {
+            lexer.next_char();
+          }
+        }
+      }
+      Some(c) => {
+        match c {
+          '[' => in_class = true,
+          ']' => in_class = false,
+          _ => {}
+        }
+        lexer.next_char();
+      }
+    }
+  };
+
+  let flags = lexer.read_while(is_identifier_part);
+
+  for (i, flag) in flags.char_indices() {
+    if !"dgimsuyv".contains(flag) {
+      return Err(LexError::new(
+        DiagnosticCode::InvalidRegexFlag,
+        format!("Unknown regular expression flag: {}", flag),
+      ));
+    }
+    if flags[..i].contains(flag) {
+      return Err(LexError::new(
+        DiagnosticCode::InvalidRegexFlag,
+        format!("Duplicate regular expression flag: {}", flag),
+      ));
+    }
+  }
+  if flags.contains('u') && flags.contains('v') {
+    return Err(LexError::new(
+      DiagnosticCode::InvalidRegexFlag,
+      String::from(
+        "The Unicode (u) flag and the Unicode Sets (v) flag cannot be set simultaneously",
+      ),
+    ));
+  }
+
+  Ok(Token::literal(Literal::Regex(pattern, flags)))
+}
+
+fn next_template<'a>(lexer: &mut Lexer<'a>) -> Option<Option<TokenResult<'a>>> {
+  Some(Some(read_template(lexer, false)))
+}
+
+/**
+ * Reads a template chunk, starting after the opening "`" (head) or the "}" closing a substitution.
+ * `text` => NoSubstitution
+ * `text${ => Head, pushes Template + Typescript
+ * }text${ => Middle, pushes Typescript
+ * }text` => Tail, pops Template
+ * The cooked value is None when it has an invalid escape, which is only valid in tagged templates.
+ * Both values borrow the source text unless they need to be decoded or normalized.
+ */
+fn read_template<'a>(lexer: &mut Lexer<'a>, is_head: bool) -> TokenResult<'a> {
+  let start = lexer.offset;
+  let mut raw: Option<String> = None; // Only once a CR has been normalized
+  let mut cooked: Option<String> = None; // Only once there's an escape or a normalized CR
+  let mut is_cooked_valid = true;
+
+  let (has_substitution, end) = loop {
+    let before = lexer.offset;
+    let c = match lexer.next_char() {
+      Some(c) => c,
+      None => {
+        if !is_head {
+          lexer.state.pop();
+        }
+        return Err(LexError::new(
+          DiagnosticCode::UnterminatedTemplate,
+          String::from("Unterminated template literal"),
+        ));
+      }
+    };
+
+    match c {
+      '`' => break (false, before),
+      '$' if lexer.peek() == Some('{') => {
+        lexer.next_char();
+        break (true, before);
+      }
+      '\\' => {
+        let escaped = read_escape(lexer, true);
+        if let Some(raw) = &mut raw {
+          raw.push_str(lexer.slice(before));
+        }
+        let cooked = cooked.get_or_insert_with(|| lexer.text[start..before].to_string());
+        match escaped {
+          Ok(Some(escaped)) => cooked.push(escaped),
+          Ok(None) => {}
+          Err(_) => is_cooked_valid = false,
+        }
+      }
+      '\r' => {
+        // CR and CRLF are normalized to LF both in cooked and raw values
+        if lexer.peek() == Some('\n') {
+          lexer.next_char();
+        }
+        let prefix = &lexer.text[start..before];
+        raw.get_or_insert_with(|| prefix.to_string()).push('\n');
+        cooked.get_or_insert_with(|| prefix.to_string()).push('\n');
+      }
+      c => {
+        if let Some(raw) = &mut raw {
+          raw.push(c);
+        }
+        if let Some(cooked) = &mut cooked {
+          cooked.push(c);
+        }
+      }
+    }
+  };
+
+  let kind = match (is_head, has_substitution) {
+    (true, false) => TokenKind::NoSubstitutionTemplate,
+    (true, true) => TokenKind::TemplateHead,
+    (false, true) => TokenKind::TemplateMiddle,
+    (false, false) => TokenKind::TemplateTail,
+  };
+
+  if has_substitution {
+    if is_head {
+      lexer.state.push(LexerState::Template);
+    }
+    lexer
+      .state
+      .push(LexerState::Typescript(TypescriptState { bracket_stack: 1 }));
+  } else if !is_head {
+    lexer.state.pop();
+  }
+
+  let source = &lexer.text[start..end];
+  let cooked = if is_cooked_valid {
+    Some(cooked.map_or(Cow::Borrowed(source), Cow::Owned))
+  } else {
+    None
+  };
+  let raw = raw.map_or(Cow::Borrowed(source), Cow::Owned);
+  Ok(Token::template(kind, TemplateChunk { cooked, raw }))
+}
+
+/**
+ * Reads an escape sequence after `\`.
+ * Returns None for line continuations, which don't produce any character.
+ * Legacy octal escapes (\01) are only valid outside templates.
+ */
+fn read_escape(lexer: &mut Lexer, is_template: bool) -> Result<Option<char>, LexError> {
+  let c = match lexer.next_char() {
+    Some(c) => c,
+    None => {
+      return Err(LexError::new(
+        DiagnosticCode::InvalidEscape,
+        String::from("Unexpected end of text"),
+      ))
+    }
+  };
+
+  let escaped = match c {
+    'n' => '\n',
+    't' => '\t',
+    'r' => '\r',
+    'b' => '\u{8}',
+    'f' => '\u{c}',
+    'v' => '\u{b}',
+    '0' if !matches!(lexer.peek(), Some(c) if c.is_ascii_digit()) => '\0',
+    '0'..='7' if !is_template => {
+      // Up to 3 octal digits, as long as the value fits in a byte
+      let mut value = c.to_digit(8).unwrap();
+      let max_digits = if c <= '3' { 3 } else { 2 };
+      for _ in 1..max_digits {
+        match lexer.peek().and_then(|c| c.to_digit(8)) {
+          Some(digit) => {
+            lexer.next_char();
+            value = value * 8 + digit;
+          }
+          None => break,
+        }
+      }
+      std::char::from_u32(value).unwrap()
+    }
+    '0'..='9' if is_template => {
+      return Err(LexError::new(
+        DiagnosticCode::InvalidEscape,
+        String::from("Octal escape sequences are not allowed in template strings"),
+      ))
+    }
+    'x' => {
+      let value = read_hex_digits(lexer, 2)?;
+      std::char::from_u32(value).unwrap()
+    }
+    'u' => return read_unicode_escape(lexer).map(Some),
+    '\r' => {
+      if lexer.peek() == Some('\n') {
+        lexer.next_char();
+      }
+      return Ok(None);
+    }
+    '\n' | '\u{2028}' | '\u{2029}' => return Ok(None),
+    c => c,
+  };
+
+  Ok(Some(escaped))
+}
+
+/**
+ * Reads the part after `\u`: either `{X...}` or exactly 4 hex digits.
+ * A surrogate pair written as two escapes (\uD83D\uDE00) is combined into a single char,
+ * lone surrogates can't be represented in a Rust string so they become U+FFFD.
+ */
+fn read_unicode_escape(lexer: &mut Lexer) -> Result<char, LexError> {
+  let value = if lexer.peek() == Some('{') {
+    lexer.next_char();
+    let mut value: u32 = 0;
+    let mut has_digits = false;
+    loop {
+      match lexer.peek() {
+        Some('}') if has_digits => {
+          lexer.next_char();
+          break;
+        }
+        Some(c) if c.is_ascii_hexdigit() => {
+          let digit = c.to_digit(16).unwrap();
+          lexer.next_char();
+          has_digits = true;
+          value = value.saturating_mul(16).saturating_add(digit);
+        }
+        _ => {
+          return Err(LexError::new(
+            DiagnosticCode::InvalidEscape,
+            String::from("Hexadecimal digit expected"),
+          ))
+        }
+      }
+    }
+    if value > 0x10FFFF {
+      return Err(LexError::new(
+        DiagnosticCode::InvalidEscape,
+        String::from("An extended Unicode escape value must be between 0x0 and 0x10FFFF inclusive"),
+      ));
+    }
+    value
+  } else {
+    read_hex_digits(lexer, 4)?
+  };
+
+  if (0xD800..0xDC00).contains(&value) {
+    let ahead = &lexer.text[lexer.offset..];
+    if ahead.starts_with("\\u") && ahead.len() >= 6 {
+      if let Ok(low) = u32::from_str_radix(&ahead[2..6], 16) {
+        if (0xDC00..0xE000).contains(&low) {
+          for _ in 0..6 {
+            lexer.next_char();
+          }
+          let combined = 0x10000 + ((value - 0xD800) << 10) + (low - 0xDC00);
+          return Ok(std::char::from_u32(combined).unwrap());
+        }
+      }
+    }
+  }
+
+  Ok(std::char::from_u32(value).unwrap_or('\u{FFFD}'))
+}
+
+fn read_hex_digits(lexer: &mut Lexer, count: usize) -> Result<u32, LexError> {
+  let mut value = 0;
+  for _ in 0..count {
+    match lexer.peek().and_then(|c| c.to_digit(16)) {
+      Some(digit) => {
+        lexer.next_char();
+        value = value * 16 + digit;
+      }
+      None => {
+        return Err(LexError::new(
+          DiagnosticCode::InvalidEscape,
+          String::from("Hexadecimal digit expected"),
+        ))
+      }
+    }
+  }
+  Ok(value)
+}
+
+/// Reads the longest punctuator starting with `first_char`, None if there isn't any.
+fn read_symbol(lexer: &mut Lexer, first_char: char) -> Option<TokenKind> {
+  if !first_char.is_ascii() {
+    return None;
+  }
+
+  // `..` isn't a symbol, so `...` can't be built by extending one char at a time
+  if first_char == '.' && lexer.text[lexer.offset..].starts_with("..") {
+    lexer.next_char();
+    lexer.next_char();
+    return Some(TokenKind::DotDotDot);
+  }
+
+  // Punctuators are at most 4 ASCII chars long, so they fit in a buffer on the stack.
+  let mut buffer = [first_char as u8; 4];
+  let mut length = 1;
+  let mut kind = TokenKind::punctuator(std::str::from_utf8(&buffer[..1]).unwrap())?;
+
+  // If there's no peek we reached the end of the program.
+  while let Some(peek) = lexer.peek() {
+    if !peek.is_ascii() || length == buffer.len() {
       break;
     }
+    buffer[length] = peek as u8;
 
-    if VALID_SYMBOLS.contains(&&raw[..]) {
-      lexer.next_char();
-    } else {
-      raw.pop();
+    // a?.5:0 is a conditional, not an optional chain
+    if kind == TokenKind::Question
+      && peek == '.'
+      && matches!(lexer.peek_nth(1), Some(c) if c.is_ascii_digit())
+    {
       break;
     }
+
+    match TokenKind::punctuator(std::str::from_utf8(&buffer[..=length]).unwrap()) {
+      Some(extended) => {
+        lexer.next_char();
+        kind = extended;
+        length += 1;
+      }
+      None => break,
+    }
+  }
+
+  Some(kind)
+}
+
+/**
+ * Numeric literals, following the ECMAScript grammar:
+ * 123 1_000 1.5 .5 1. 1e10 1.5E-3 => Decimal
+ * 0xFF 0b1010 0o17 => Decimal (non-decimal radix)
+ * 10n 0xFFn => BigInt
+ * Legacy octals (017) and leading zeroes (08) are rejected like TS does.
+ * The raw text is kept as written so numeric literal types can be printed back.
+ */
+fn read_number<'a>(lexer: &mut Lexer<'a>, first_char: char) -> TokenResult<'a> {
+  let start = lexer.offset - first_char.len_utf8();
+  let mut error: Option<String> = None;
+
+  let radix = match (first_char, lexer.peek()) {
+    ('0', Some('x')) | ('0', Some('X')) => 16,
+    ('0', Some('o')) | ('0', Some('O')) => 8,
+    ('0', Some('b')) | ('0', Some('B')) => 2,
+    _ => 10,
+  };
+
+  let mut is_integer = true;
+  if radix != 10 {
+    lexer.next_char();
+    let digits_start = lexer.offset;
+    read_digits(lexer, radix, false, &mut error);
+    if lexer.offset == digits_start {
+      error.get_or_insert_with(|| String::from("Digit expected"));
+    }
+  } else {
+    if first_char == '.' {
+      is_integer = false;
+      read_digits(lexer, 10, false, &mut error);
+    } else {
+      read_digits(lexer, 10, true, &mut error);
+
+      let raw = lexer.slice(start);
+      if first_char == '0' && raw.len() > 1 {
+        error.get_or_insert_with(|| {
+          if raw.chars().all(|c| c < '8') {
+            format!(
+              "Octal literals are not allowed. Use the syntax '0o{}'",
+              &raw[1..]
+            )
+          } else {
+            format!("Decimals with leading zeros are not allowed: {}", raw)
+          }
+        });
+      }
+
+      if lexer.peek() == Some('.') {
+        is_integer = false;
+        lexer.next_char();
+        read_digits(lexer, 10, false, &mut error);
+      }
+    }
+
+    if matches!(lexer.peek(), Some('e') | Some('E')) {
+      is_integer = false;
+      lexer.next_char();
+      if matches!(lexer.peek(), Some('+') | Some('-')) {
+        lexer.next_char();
+      }
+      let digits_start = lexer.offset;
+      read_digits(lexer, 10, false, &mut error);
+      if lexer.offset == digits_start {
+        error.get_or_insert_with(|| String::from("Digit expected"));
+      }
+    }
+  }
+
+  let is_bigint = lexer.peek() == Some('n');
+  if is_bigint {
+    lexer.next_char();
+    if !is_integer {
+      error.get_or_insert_with(|| {
+        format!(
+          "A bigint literal must be an integer: {}",
+          lexer.slice(start)
+        )
+      });
+    }
+  }
+
+  if matches!(lexer.peek(), Some(c) if is_identifier_start(c)) {
+    // Consume the rest so that `3in` is reported once instead of as `3` `in`
+    lexer.read_while(is_identifier_part);
+    error.get_or_insert_with(|| {
+      format!(
+        "An identifier or keyword cannot immediately follow a numeric literal: {}",
+        lexer.slice(start)
+      )
+    });
+  }
+
+  if let Some(error) = error {
+    return Err(LexError::new(DiagnosticCode::InvalidNumber, error));
+  }
+
+  let raw = lexer.slice(start);
+  if is_bigint {
+    return Ok(Token::literal(Literal::BigInt(raw)));
+  }
+
+  let digits = if raw.contains('_') {
+    Cow::Owned(raw.replace('_', ""))
+  } else {
+    Cow::Borrowed(raw)
+  };
+  let value = if radix == 10 {
+    digits.parse::<f64>().unwrap()
+  } else {
+    digits[2..].chars().fold(0.0, |acc, c| {
+      acc * radix as f64 + c.to_digit(radix).unwrap() as f64
+    })
+  };
+
+  Ok(Token::literal(Literal::Decimal(value, raw)))
+}
+
+/**
+ * Reads a run of digits in `radix`, allowing single `_` separators between them.
+ * `has_digit` tells whether the digit run already started (the first char of the literal).
+ */
+fn read_digits(lexer: &mut Lexer, radix: u32, has_digit: bool, error: &mut Option<String>) {
+  let mut prev_is_digit = has_digit;
+  let mut prev_is_separator = false;
+
+  loop {
+    match lexer.peek() {
+      Some('_') => {
+        if prev_is_separator {
+          error.get_or_insert_with(|| {
+            String::from("Multiple consecutive numeric separators are not permitted")
+          });
+        } else if !prev_is_digit {
+          error.get_or_insert_with(|| String::from("Numeric separators are not allowed here"));
+        }
+        lexer.next_char();
+        prev_is_digit = false;
+        prev_is_separator = true;
+      }
+      Some(c) if c.is_digit(radix) => {
+        lexer.next_char();
+        prev_is_digit = true;
+        prev_is_separator = false;
+      }
+      _ => break,
+    }
   }
 
-  return raw;
+  if prev_is_separator {
+    error.get_or_insert_with(|| String::from("Numeric separators are not allowed here"));
+  }
 }
 
-fn is_identifier(c: char) -> bool {
-  c.is_ascii_alphanumeric() || c == '_' || c == '$'
+const BOM: char = '\u{FEFF}';
+
+// The BOM is whitespace in JS, not only at the start of the file
+fn is_whitespace(c: char) -> bool {
+  c.is_whitespace() || c == BOM
+}
+
+fn is_line_terminator(c: char) -> bool {
+  matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+/// Reads a JSX name after its already consumed first char: no escapes, `-` is allowed after the start.
+fn read_name<'a>(lexer: &mut Lexer<'a>, first_char: char) -> &'a str {
+  let start = lexer.offset - first_char.len_utf8();
+  lexer.read_while(|c| is_identifier_part(c) || c == '-');
+  lexer.slice(start)
+}
+
+/// JSX attribute strings can span multiple lines and don't have escapes, only entities.
+fn read_jsx_string<'a>(lexer: &mut Lexer<'a>, quote: char) -> TokenResult<'a> {
+  let value = lexer.read_while(|c| c != quote);
+
+  match lexer.next_char() {
+    Some(_) => Ok(Token::literal(Literal::Str(decode_entities(value), value))),
+    None => Err(LexError::new(
+      DiagnosticCode::UnterminatedString,
+      String::from("Unterminated string literal"),
+    )),
+  }
+}
+
+/**
+ * Identifiers are ID_Start ID_Continue*, plus `$` and `_` anywhere and ZWNJ/ZWJ after the start.
+ * They can contain `\uXXXX` or `\u{X}` escapes of those chars: `caf\u00e9` is `café`.
+ * Returns the decoded name, borrowed from the source if it has no escapes, and whether it had any.
+ */
+fn read_identifier<'a>(
+  lexer: &mut Lexer<'a>,
+  first_char: char,
+) -> Result<(Cow<'a, str>, bool), LexError> {
+  let start = lexer.offset - first_char.len_utf8();
+  let mut name: Option<String> = None; // Only once there's an escape
+  let mut next = Some(first_char);
+
+  while let Some(c) = next {
+    if c == '\\' {
+      let name =
+        name.get_or_insert_with(|| lexer.text[start..lexer.offset - c.len_utf8()].to_string());
+      if lexer.next_char() != Some('u') {
+        return Err(LexError::new(
+          DiagnosticCode::InvalidCharacter,
+          String::from("Invalid character: \\"),
+        ));
+      }
+      let c = read_unicode_escape(lexer)?;
+      let is_valid = if name.is_empty() {
+        is_identifier_start(c)
+      } else {
+        is_identifier_part(c)
+      };
+      if !is_valid {
+        return Err(LexError::new(
+          DiagnosticCode::InvalidIdentifier,
+          format!("Invalid Unicode escape sequence in identifier: {:?}", c),
+        ));
+      }
+      name.push(c);
+    } else if let Some(name) = &mut name {
+      name.push(c);
+    }
+
+    next = match lexer.peek() {
+      Some(c) if c == '\\' || is_identifier_part(c) => lexer.next_char(),
+      _ => None,
+    };
+  }
+
+  Ok(match name {
+    Some(name) => (Cow::Owned(name), true),
+    None => (Cow::Borrowed(lexer.slice(start)), false),
+  })
+}
+
+fn is_identifier_start(c: char) -> bool {
+  if c.is_ascii() {
+    c.is_ascii_alphabetic() || c == '_' || c == '$'
+  } else {
+    unicode_ident::is_xid_start(c)
+  }
+}
+
+fn is_identifier_part(c: char) -> bool {
+  if c.is_ascii() {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+  } else {
+    unicode_ident::is_xid_continue(c) || c == '\u{200C}' || c == '\u{200D}'
+  }
 }
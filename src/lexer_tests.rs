@@ -1,9 +1,16 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod lexer_tests {
   // Note this useful idiom: importing names from outer (for mod tests) scope.
-  use crate::lexer::{Lexer, TokenResult};
-  use crate::tokens::{Literal, Token};
+  use crate::atom::Atom;
+  use crate::lexer::{
+    read_source, DiagnosticCode, LanguageVariant, LexDiagnostic, Lexer, LocatedToken, Trivia,
+    TriviaKind,
+  };
+  use crate::span::{Span, TextEdit};
+  use crate::tokens::{JsxText, Literal, TemplateChunk, Token, TokenKind, TokenValue};
   use itertools::assert_equal;
+  use std::borrow::Cow;
   use std::vec::IntoIter;
 
   #[test]
@@ -15,7 +22,7 @@ mod lexer_tests {
       symbol(":"),
       identifier("MyType"),
       symbol("="),
-      i_literal(5),
+      i_literal("5"),
       symbol(";"),
     ]
     .into_iter();
@@ -29,14 +36,379 @@ mod lexer_tests {
     let result = vec![
       s_literal("double' quote"),
       s_literal("single\" quote"),
-      i_literal(0),
-      i_literal(123),
+      i_literal("0"),
+      i_literal("123"),
     ]
     .into_iter();
 
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn string_escapes() {
+    let lexer = Lexer::from_text(
+      "'it\\'s' \"\\n\\t\\\\\" '\\u{1F600}\\uD83D\\uDE00\\u0041\\x41' 'line\\\ncontinuation' '\\0\\101\\8'",
+    );
+    let result = vec![
+      str_literal("it's", "it\\'s"),
+      str_literal("\n\t\\", "\\n\\t\\\\"),
+      str_literal("😀😀AA", "\\u{1F600}\\uD83D\\uDE00\\u0041\\x41"),
+      str_literal("linecontinuation", "line\\\ncontinuation"),
+      str_literal("\0A8", "\\0\\101\\8"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn literals_borrow_the_source() {
+    let text = "'plain' 'esc\\n' `tpl` `a\r\nb`";
+    let tokens: Vec<_> = Lexer::from_text(text).map(|t| t.token).collect();
+    let is_borrowed = |token: &Token| match &token.value {
+      Some(TokenValue::Literal(Literal::Str(value, _))) => matches!(value, Cow::Borrowed(_)),
+      Some(TokenValue::Template(chunk)) => matches!(chunk.raw, Cow::Borrowed(_)),
+      _ => false,
+    };
+
+    assert_eq!(
+      tokens.iter().map(is_borrowed).collect::<Vec<_>>(),
+      vec![true, false, true, false]
+    );
+  }
+
+  #[test]
+  fn invalid_strings() {
+    let lexer = Lexer::from_text("'\\x4' '\\u{110000}' 'new\nline' \"eof");
+    let result = vec![
+      Err(String::from("Hexadecimal digit expected")),
+      Err(String::from(
+        "An extended Unicode escape value must be between 0x0 and 0x10FFFF inclusive",
+      )),
+      Err(String::from("Unterminated string literal")),
+      identifier("line"),
+      Err(String::from("Unterminated string literal")),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn regex_vs_division() {
+    let lexer =
+      Lexer::from_text("const re = /ab+c/gi; a / b / c; (x) / 2; return /[/]\\//.test(s)");
+    let result = vec![
+      keyword("const"),
+      identifier("re"),
+      symbol("="),
+      regex("ab+c", "gi"),
+      symbol(";"),
+      identifier("a"),
+      symbol("/"),
+      identifier("b"),
+      symbol("/"),
+      identifier("c"),
+      symbol(";"),
+      symbol("("),
+      identifier("x"),
+      symbol(")"),
+      symbol("/"),
+      i_literal("2"),
+      symbol(";"),
+      keyword("return"),
+      regex("[/]\\/", ""),
+      symbol("."),
+      identifier("test"),
+      symbol("("),
+      identifier("s"),
+      symbol(")"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn division_after_contextual_keywords() {
+    let lexer = Lexer::from_text("x = number / 2 / 3; set / 2 / 3; type / 2 / 1");
+    let result = vec![
+      identifier("x"),
+      symbol("="),
+      keyword("number"),
+      symbol("/"),
+      i_literal("2"),
+      symbol("/"),
+      i_literal("3"),
+      symbol(";"),
+      keyword("set"),
+      symbol("/"),
+      i_literal("2"),
+      symbol("/"),
+      i_literal("3"),
+      symbol(";"),
+      keyword("type"),
+      symbol("/"),
+      i_literal("2"),
+      symbol("/"),
+      i_literal("1"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn regex_after_contextual_keywords() {
+    let lexer = Lexer::from_text("await /x/; for (a of /x/g) {}");
+    let result = vec![
+      keyword("await"),
+      regex("x", ""),
+      symbol(";"),
+      keyword("for"),
+      symbol("("),
+      identifier("a"),
+      keyword("of"),
+      regex("x", "g"),
+      symbol(")"),
+      symbol("{"),
+      symbol("}"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn regex_in_substitutions() {
+    let lexer = Lexer::from_text("`${/a/}` + f(/b/, [/c/]) // comment");
+    let result = vec![
+      template(TokenKind::TemplateHead, ""),
+      regex("a", ""),
+      template(TokenKind::TemplateTail, ""),
+      symbol("+"),
+      identifier("f"),
+      symbol("("),
+      regex("b", ""),
+      symbol(","),
+      symbol("["),
+      regex("c", ""),
+      symbol("]"),
+      symbol(")"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn invalid_regex() {
+    let lexer = Lexer::from_text("x = /a/gg; x = /a/x; x = /a/uv; x = /a\n");
+    let result = vec![
+      identifier("x"),
+      symbol("="),
+      Err(String::from("Duplicate regular expression flag: g")),
+      symbol(";"),
+      identifier("x"),
+      symbol("="),
+      Err(String::from("Unknown regular expression flag: x")),
+      symbol(";"),
+      identifier("x"),
+      symbol("="),
+      Err(String::from(
+        "The Unicode (u) flag and the Unicode Sets (v) flag cannot be set simultaneously",
+      )),
+      symbol(";"),
+      identifier("x"),
+      symbol("="),
+      Err(String::from("Unterminated regular expression literal")),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn numeric_literals() {
+    let lexer = Lexer::from_text(
+      "1.5 .5 1. 1e10 2E-3 1.5e+2 0xFF 0b1010 0o17 1_000_000 0x_ff 4294967296 10n 0xFFn 0",
+    );
+    let result = vec![
+      d_literal("1.5", 1.5),
+      d_literal(".5", 0.5),
+      d_literal("1.", 1.0),
+      d_literal("1e10", 1e10),
+      d_literal("2E-3", 2e-3),
+      d_literal("1.5e+2", 150.0),
+      d_literal("0xFF", 255.0),
+      d_literal("0b1010", 10.0),
+      d_literal("0o17", 15.0),
+      d_literal("1_000_000", 1_000_000.0),
+      Err(String::from("Numeric separators are not allowed here")),
+      d_literal("4294967296", 4294967296.0),
+      b_literal("10n"),
+      b_literal("0xFFn"),
+      i_literal("0"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn numeric_literals_followed_by_symbols() {
+    let lexer = Lexer::from_text("1..toString() a.b[0].c 1.5.d");
+    let result = vec![
+      d_literal("1.", 1.0),
+      symbol("."),
+      identifier("toString"),
+      symbol("("),
+      symbol(")"),
+      identifier("a"),
+      symbol("."),
+      identifier("b"),
+      symbol("["),
+      i_literal("0"),
+      symbol("]"),
+      symbol("."),
+      identifier("c"),
+      d_literal("1.5", 1.5),
+      symbol("."),
+      identifier("d"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn invalid_numeric_literals() {
+    let lexer = Lexer::from_text("1__0 1_ 017 08 1.5n 1e 0x 3in");
+    let result = vec![
+      Err(String::from(
+        "Multiple consecutive numeric separators are not permitted",
+      )),
+      Err(String::from("Numeric separators are not allowed here")),
+      Err(String::from(
+        "Octal literals are not allowed. Use the syntax '0o17'",
+      )),
+      Err(String::from(
+        "Decimals with leading zeros are not allowed: 08",
+      )),
+      Err(String::from("A bigint literal must be an integer: 1.5n")),
+      Err(String::from("Digit expected")),
+      Err(String::from("Digit expected")),
+      Err(String::from(
+        "An identifier or keyword cannot immediately follow a numeric literal: 3in",
+      )),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn operators() {
+    let lexer = Lexer::from_text(
+      "a % b ** c++ --d; e += f -= g *= h /= i %= j **= k <<= l >>= m >>>= n &= o |= p ^= q;
+      r &&= s ||= t ??= u & v | w ^ ~x << y >> z >>> a; [...b] @c #d a?.5:0 a?.b",
+    );
+    let result = vec![
+      identifier("a"),
+      symbol("%"),
+      identifier("b"),
+      symbol("**"),
+      identifier("c"),
+      symbol("++"),
+      symbol("--"),
+      identifier("d"),
+      symbol(";"),
+      identifier("e"),
+      symbol("+="),
+      identifier("f"),
+      symbol("-="),
+      identifier("g"),
+      symbol("*="),
+      identifier("h"),
+      symbol("/="),
+      identifier("i"),
+      symbol("%="),
+      identifier("j"),
+      symbol("**="),
+      identifier("k"),
+      symbol("<<="),
+      identifier("l"),
+      symbol(">>="),
+      identifier("m"),
+      symbol(">>>="),
+      identifier("n"),
+      symbol("&="),
+      identifier("o"),
+      symbol("|="),
+      identifier("p"),
+      symbol("^="),
+      identifier("q"),
+      symbol(";"),
+      identifier("r"),
+      symbol("&&="),
+      identifier("s"),
+      symbol("||="),
+      identifier("t"),
+      symbol("??="),
+      identifier("u"),
+      symbol("&"),
+      identifier("v"),
+      symbol("|"),
+      identifier("w"),
+      symbol("^"),
+      symbol("~"),
+      identifier("x"),
+      symbol("<<"),
+      identifier("y"),
+      symbol(">>"),
+      identifier("z"),
+      symbol(">>>"),
+      identifier("a"),
+      symbol(";"),
+      symbol("["),
+      symbol("..."),
+      identifier("b"),
+      symbol("]"),
+      symbol("@"),
+      identifier("c"),
+      symbol("#"),
+      identifier("d"),
+      identifier("a"),
+      symbol("?"),
+      d_literal(".5", 0.5),
+      symbol(":"),
+      i_literal("0"),
+      identifier("a"),
+      symbol("?."),
+      identifier("b"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn split_nested_generics() {
+    let lexer = Lexer::from_text("let x: Map<K, Array<T>>");
+    let tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+    assert_eq!(tokens.last(), Some(&symbol_token(">>")));
+
+    let (first, rest) = tokens.last().unwrap().split_greater().unwrap();
+    assert_eq!(first, symbol_token(">"));
+    assert_eq!(rest, symbol_token(">"));
+
+    let (first, rest) = symbol_token(">>>=").split_greater().unwrap();
+    assert_eq!(first, symbol_token(">"));
+    assert_eq!(rest, symbol_token(">>="));
+
+    assert_eq!(symbol_token(">").split_greater(), None);
+  }
+
   #[test]
   fn identifier_vs_keyword() {
     let lexer = Lexer::from_text("afunction function functiona");
@@ -50,6 +422,106 @@ mod lexer_tests {
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn keyword_categories() {
+    let kinds: Vec<_> = Lexer::from_text("class this let yield type from as of")
+      .map(|t| t.token.kind)
+      .collect();
+    let reserved: Vec<_> = kinds.iter().map(|k| k.is_reserved_word()).collect();
+    let strict: Vec<_> = kinds
+      .iter()
+      .map(|k| k.is_strict_mode_reserved_word())
+      .collect();
+    let contextual: Vec<_> = kinds.iter().map(|k| k.is_identifier()).collect();
+
+    assert_eq!(
+      reserved,
+      vec![true, true, false, false, false, false, false, false]
+    );
+    assert_eq!(
+      strict,
+      vec![false, false, true, true, false, false, false, false]
+    );
+    assert_eq!(
+      contextual,
+      vec![false, false, false, false, true, true, true, true]
+    );
+  }
+
+  #[test]
+  fn contextual_keywords_have_a_name() {
+    let names: Vec<_> = Lexer::from_text("type x \\u0074ype class")
+      .map(|t| t.token.name())
+      .collect();
+
+    assert_eq!(
+      names,
+      vec![
+        Some(Atom::new("type")),
+        Some(Atom::new("x")),
+        Some(Atom::new("type")),
+        None
+      ]
+    );
+  }
+
+  #[test]
+  fn unicode_identifiers() {
+    let lexer = Lexer::from_text("café π $_ ℮x a\u{200C}b x٣ 日本語");
+    let result = vec![
+      identifier("café"),
+      identifier("π"),
+      identifier("$_"),
+      identifier("℮x"),
+      identifier("a\u{200C}b"),
+      identifier("x٣"),
+      identifier("日本語"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn escaped_identifiers() {
+    let lexer = Lexer::from_text("caf\\u00e9 \\u{3C0}x \\u0061s \\u0069f a\\u0020 \\u0031a");
+    let result = vec![
+      identifier("café"),
+      identifier("πx"),
+      identifier("as"),
+      Err(String::from(
+        "Keywords cannot contain escape characters: if",
+      )),
+      Err(String::from(
+        "Invalid Unicode escape sequence in identifier: ' '",
+      )),
+      Err(String::from(
+        "Invalid Unicode escape sequence in identifier: '1'",
+      )),
+      identifier("a"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn jsx_name_starting_with_digit() {
+    let lexer = Lexer::from_text("<Element a 1a=\"\" />");
+    let result = vec![
+      symbol("<"),
+      identifier("Element"),
+      identifier("a"),
+      Err(String::from("JSX names can't start with a digit: 1a")),
+      symbol("="),
+      s_literal(""),
+      symbol("/>"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
   #[test]
   fn fake_jsx() {
     let lexer = Lexer::from_text("var fn = <T extends any>() => void 0");
@@ -60,33 +532,168 @@ mod lexer_tests {
       symbol("<"),
       identifier("T"),
       keyword("extends"),
-      identifier("any"),
+      keyword("any"),
+      symbol(">"),
+      symbol("("),
+      symbol(")"),
+      symbol("=>"),
+      keyword("void"),
+      i_literal("0"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+
+    let lexer = Lexer::from_text("3 < value || value > 5");
+    let result = vec![
+      i_literal("3"),
+      symbol("<"),
+      identifier("value"),
+      symbol("||"),
+      identifier("value"),
+      symbol(">"),
+      i_literal("5"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn jsx_or_type_parameters() {
+    let lexer = Lexer::from_text("a < b > c; f = <T,>() => 0; g = <const T extends U>() => 0");
+    let result = vec![
+      identifier("a"),
+      symbol("<"),
+      identifier("b"),
+      symbol(">"),
+      identifier("c"),
+      symbol(";"),
+      identifier("f"),
+      symbol("="),
+      symbol("<"),
+      identifier("T"),
+      symbol(","),
+      symbol(">"),
+      symbol("("),
+      symbol(")"),
+      symbol("=>"),
+      i_literal("0"),
+      symbol(";"),
+      identifier("g"),
+      symbol("="),
+      symbol("<"),
+      keyword("const"),
+      identifier("T"),
+      keyword("extends"),
+      identifier("U"),
+      symbol(">"),
+      symbol("("),
+      symbol(")"),
+      symbol("=>"),
+      i_literal("0"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+
+    // An attribute named `extends`
+    let lexer = Lexer::from_text("<T extends=\"\" />");
+    let result = vec![
+      symbol("<"),
+      identifier("T"),
+      identifier("extends"),
+      symbol("="),
+      s_literal(""),
+      symbol("/>"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn type_assertions_without_jsx() {
+    for variant in [LanguageVariant::Ts, LanguageVariant::Dts] {
+      let lexer = Lexer::from_text("x = <T>value;").with_variant(variant);
+      let result = vec![
+        identifier("x"),
+        symbol("="),
+        symbol("<"),
+        identifier("T"),
+        symbol(">"),
+        identifier("value"),
+        symbol(";"),
+      ]
+      .into_iter();
+
+      assert_result(lexer, result);
+    }
+  }
+
+  #[test]
+  fn rescan_less_than() {
+    let mut lexer = Lexer::from_text("type F = <T>(a: T) => T");
+    let mut next = || token_result(lexer.next().unwrap());
+    assert_eq!(
+      vec![next(), next(), next(), next()],
+      vec![keyword("type"), identifier("F"), symbol("="), symbol("<")]
+    );
+    lexer.rescan_as_less_than();
+    let result = vec![
+      identifier("T"),
       symbol(">"),
       symbol("("),
+      identifier("a"),
+      symbol(":"),
+      identifier("T"),
       symbol(")"),
       symbol("=>"),
-      identifier("void"),
-      i_literal(0),
+      identifier("T"),
     ]
     .into_iter();
 
     assert_result(lexer, result);
 
-    let lexer = Lexer::from_text("3 < value || value > 5");
+    let mut lexer = Lexer::from_text("function f() {}\n<a>text</a>");
+    let less_than = lexer.by_ref().nth(6).unwrap();
+    assert_eq!(token_result(less_than), symbol("<"));
+    lexer.rescan_as_jsx_element();
     let result = vec![
-      i_literal(3),
-      symbol("<"),
-      identifier("value"),
-      symbol("||"),
-      identifier("value"),
+      identifier("a"),
+      symbol(">"),
+      jsx_text("text"),
+      symbol("</"),
+      identifier("a"),
       symbol(">"),
-      i_literal(5),
     ]
     .into_iter();
 
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn language_variants() {
+    let variants = ["a.ts", "a.d.ts", "a.tsx", "a.mjs", "a.jsx", "a.json"]
+      .iter()
+      .map(|path| LanguageVariant::from_path(path))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      variants,
+      vec![
+        Some(LanguageVariant::Ts),
+        Some(LanguageVariant::Dts),
+        Some(LanguageVariant::Tsx),
+        Some(LanguageVariant::Js),
+        Some(LanguageVariant::Jsx),
+        None,
+      ]
+    );
+    assert!(LanguageVariant::Js.allows_jsx());
+    assert!(!LanguageVariant::Dts.allows_jsx());
+  }
+
   #[test]
   fn real_jsx() {
     let lexer = Lexer::from_text("let fn = <T>() => void 0</T>");
@@ -97,7 +704,7 @@ mod lexer_tests {
       symbol("<"),
       identifier("T"),
       symbol(">"),
-      s_literal("() => void 0"),
+      jsx_text("() => void 0"),
       symbol("</"),
       identifier("T"),
       symbol(">"),
@@ -128,7 +735,7 @@ mod lexer_tests {
       identifier("prop3"),
       symbol("="),
       symbol("{"),
-      i_literal(3),
+      i_literal("3"),
       symbol("}"),
       symbol("/>"),
     ]
@@ -141,10 +748,10 @@ mod lexer_tests {
       symbol("<"),
       identifier("Elm"),
       symbol("<"),
-      identifier("G"), // TODO i_literal(3)
+      identifier("G"), // TODO i_literal("3")
       symbol(">"),
       symbol(">"),
-      s_literal("body"),
+      jsx_text("body"),
       symbol("</"),
       identifier("Elm"),
       symbol(">"),
@@ -213,7 +820,7 @@ mod lexer_tests {
       symbol("<"),
       identifier("Elm2"),
       symbol(">"),
-      s_literal("text"),
+      jsx_text("text"),
       symbol("</"),
       identifier("Elm2"),
       symbol(">"),
@@ -231,7 +838,20 @@ mod lexer_tests {
     let result = vec![
       symbol("<"),
       identifier("Element"),
-      identifier("prop"), // TODO removing this one makes the test fail
+      identifier("prop"),
+      identifier("interface"),
+      symbol("="),
+      s_literal("hello"),
+      symbol("/>"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+
+    let lexer = Lexer::from_text("<Element interface=\"hello\" />");
+    let result = vec![
+      symbol("<"),
+      identifier("Element"),
       identifier("interface"),
       symbol("="),
       s_literal("hello"),
@@ -242,6 +862,243 @@ mod lexer_tests {
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn jsx_namespaced_and_hyphenated_names() {
+    let lexer = Lexer::from_text(
+      "<svg data-test-id=\"icon\"><svg:rect xlink:href=\"#a\" aria-label=\"b\" /></svg:rect></svg>",
+    );
+    let result = vec![
+      symbol("<"),
+      identifier("svg"),
+      identifier("data-test-id"),
+      symbol("="),
+      s_literal("icon"),
+      symbol(">"),
+      symbol("<"),
+      identifier("svg"),
+      symbol(":"),
+      identifier("rect"),
+      identifier("xlink"),
+      symbol(":"),
+      identifier("href"),
+      symbol("="),
+      s_literal("#a"),
+      identifier("aria-label"),
+      symbol("="),
+      s_literal("b"),
+      symbol("/>"),
+      symbol("</"),
+      identifier("svg"),
+      symbol(":"),
+      identifier("rect"),
+      symbol(">"),
+      symbol("</"),
+      identifier("svg"),
+      symbol(">"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn jsx_spread_and_boolean_attributes() {
+    let lexer = Lexer::from_text("<Input disabled {...props} value={1} required />");
+    let result = vec![
+      symbol("<"),
+      identifier("Input"),
+      identifier("disabled"),
+      symbol("{"),
+      symbol("..."),
+      identifier("props"),
+      symbol("}"),
+      identifier("value"),
+      symbol("="),
+      symbol("{"),
+      i_literal("1"),
+      symbol("}"),
+      identifier("required"),
+      symbol("/>"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn jsx_element_attribute_values() {
+    let lexer = Lexer::from_text(
+      "<Button icon=<Icon name=\"x\" /> label=<b>Hi</b> empty=<></> title=\"t\" />",
+    );
+    let result = vec![
+      symbol("<"),
+      identifier("Button"),
+      identifier("icon"),
+      symbol("="),
+      symbol("<"),
+      identifier("Icon"),
+      identifier("name"),
+      symbol("="),
+      s_literal("x"),
+      symbol("/>"),
+      identifier("label"),
+      symbol("="),
+      symbol("<"),
+      identifier("b"),
+      symbol(">"),
+      jsx_text("Hi"),
+      symbol("</"),
+      identifier("b"),
+      symbol(">"),
+      identifier("empty"),
+      symbol("="),
+      symbol("<>"),
+      symbol("</>"),
+      identifier("title"),
+      symbol("="),
+      s_literal("t"),
+      symbol("/>"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn jsx_invalid_tokens() {
+    let mut lexer = Lexer::from_text("<a>text</a!>\n<b c=></b>").skip(6);
+    let error = lexer.next().unwrap();
+
+    assert_eq!((error.line, error.col), (1, 11));
+    assert_eq!(
+      token_result(error),
+      Err(String::from(
+        "Unexpected ! in JSX closing element, expected a name or >"
+      ))
+    );
+    assert_equal(
+      lexer.map(token_result),
+      vec![
+        symbol(">"),
+        symbol("<"),
+        identifier("b"),
+        identifier("c"),
+        symbol("="),
+        Err(String::from("Expected a JSX attribute value, found >")),
+        symbol(">"),
+        symbol("</"),
+        identifier("b"),
+        symbol(">"),
+      ],
+    );
+  }
+
+  #[test]
+  fn invalid_character_is_an_error_token() {
+    let tokens: Vec<_> = Lexer::from_text("let a = 1 € b").collect();
+    let kinds: Vec<_> = tokens.iter().map(|t| t.token.kind).collect();
+
+    assert_eq!(
+      kinds,
+      vec![
+        TokenKind::Let,
+        TokenKind::Identifier,
+        TokenKind::Equals,
+        TokenKind::NumericLiteral,
+        TokenKind::Error,
+        TokenKind::Identifier,
+      ]
+    );
+    assert_eq!(
+      tokens[4].diagnostic,
+      Some(LexDiagnostic {
+        code: DiagnosticCode::InvalidCharacter,
+        span: Span::new(10, 13),
+        message: String::from("Unknown token: €"),
+      })
+    );
+    assert_eq!(tokens[5].result(), Ok(&Token::identifier(Atom::new("b"))));
+  }
+
+  #[test]
+  fn collects_all_diagnostics() {
+    let mut lexer = Lexer::from_text("a € b\\u00\nc = 'd\ne = 0b2");
+    let kinds: Vec<_> = lexer.by_ref().map(|t| t.token.kind).collect();
+    let codes: Vec<_> = lexer.diagnostics().iter().map(|d| d.code).collect();
+
+    assert_eq!(
+      kinds,
+      vec![
+        TokenKind::Identifier,
+        TokenKind::Error,
+        TokenKind::Error,
+        TokenKind::Identifier,
+        TokenKind::Equals,
+        TokenKind::Error,
+        TokenKind::Identifier,
+        TokenKind::Equals,
+        TokenKind::Error, // 0b
+        TokenKind::NumericLiteral,
+      ]
+    );
+    assert_eq!(
+      codes,
+      vec![
+        DiagnosticCode::InvalidCharacter,
+        DiagnosticCode::InvalidEscape,
+        DiagnosticCode::UnterminatedString,
+        DiagnosticCode::InvalidNumber,
+      ]
+    );
+  }
+
+  #[test]
+  fn unbalanced_close_brace_is_an_error_token() {
+    let tokens: Vec<_> = Lexer::from_text("}").collect();
+
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(
+      tokens[0].diagnostic,
+      Some(LexDiagnostic {
+        code: DiagnosticCode::UnbalancedBrace,
+        span: Span::new(0, 1),
+        message: String::from("Unexpected }"),
+      })
+    );
+
+    let kinds: Vec<_> = Lexer::from_text("function f() {}} a")
+      .map(|t| t.token.kind)
+      .collect();
+    assert_eq!(
+      kinds,
+      vec![
+        TokenKind::Function,
+        TokenKind::Identifier,
+        TokenKind::OpenParen,
+        TokenKind::CloseParen,
+        TokenKind::OpenBrace,
+        TokenKind::CloseBrace,
+        TokenKind::Error,
+        TokenKind::Identifier,
+      ]
+    );
+  }
+
+  #[test]
+  fn rewind_forgets_diagnostics() {
+    let mut lexer = Lexer::from_text("a € b");
+    lexer.next();
+    let checkpoint = lexer.checkpoint();
+    let error = lexer.next().unwrap();
+
+    assert_eq!(error.token.kind, TokenKind::Error);
+    assert_eq!(lexer.diagnostics().len(), 1);
+    lexer.rewind(&checkpoint);
+    assert!(lexer.diagnostics().is_empty());
+    assert_eq!(lexer.next().unwrap().span, error.span);
+    assert_eq!(lexer.diagnostics().len(), 1);
+  }
+
   #[test]
   fn jsx_children_to_ts() {
     let lexer = Lexer::from_text(
@@ -253,14 +1110,14 @@ mod lexer_tests {
       symbol("<"),
       identifier("Elm"),
       symbol(">"),
-      s_literal("Some text"),
+      jsx_text("Some text"),
       symbol("{"),
       identifier("render_element"),
       symbol("("),
       symbol("<"),
       identifier("Elm"),
       symbol(">"),
-      s_literal("text"),
+      jsx_text("text"),
       symbol("</"),
       identifier("Elm"),
       symbol(">"),
@@ -269,7 +1126,7 @@ mod lexer_tests {
       symbol("}"),
       symbol(")"),
       symbol("}"),
-      s_literal("More text"),
+      jsx_text("More text"),
       symbol("</"),
       identifier("Elm"),
       symbol(">"),
@@ -290,20 +1147,21 @@ mod lexer_tests {
       symbol("<"),
       identifier("Parent"),
       symbol(">"),
+      jsx_text_value("\n        ", None),
       symbol("<"),
       identifier("Child"),
       identifier("foo"),
       identifier("bar"),
       symbol(">"),
-      s_literal("foo"),
+      jsx_text("foo"),
       symbol("</"),
       identifier("Child"),
       symbol(">"),
-      s_literal("foo bar"),
+      jsx_text_value("\n        foo bar", Some("foo bar")),
       symbol("<>"),
-      s_literal("yo"),
+      jsx_text("yo"),
       symbol("</>"),
-      s_literal("foo"),
+      jsx_text("foo"),
       symbol("</"),
       identifier("Parent"),
       symbol(">"),
@@ -318,7 +1176,7 @@ mod lexer_tests {
     let lexer = Lexer::from_text("<>body {child}</> === element");
     let result = vec![
       symbol("<>"),
-      s_literal("body "),
+      jsx_text("body "),
       symbol("{"),
       identifier("child"),
       symbol("}"),
@@ -331,6 +1189,57 @@ mod lexer_tests {
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn jsx_text_whitespace() {
+    let lexer = Lexer::from_text(
+      "<a>  leading and trailing  </a>;<a>
+        first line
+          second\tline  \r\n
+      </a>;<a> </a>;<a>\n\t\n</a>",
+    );
+    let texts: Vec<_> = lexer
+      .filter_map(|t| match t.token.value {
+        Some(TokenValue::JsxText(text)) => Some(text.value),
+        _ => None,
+      })
+      .collect();
+
+    assert_eq!(
+      texts,
+      vec![
+        Some(Cow::from("  leading and trailing  ")),
+        Some(Cow::from("first line second\tline")),
+        Some(Cow::from(" ")),
+        None,
+      ]
+    );
+  }
+
+  #[test]
+  fn jsx_entities() {
+    let lexer = Lexer::from_text(
+      "<a title=\"&quot;Tom&quot; &amp; Jerry\">&lt;&#x27;&#39;&nbsp;&copy;&#x1F600; &unknown; & &#xD800;&#32;</a>",
+    );
+    let result = vec![
+      symbol("<"),
+      identifier("a"),
+      identifier("title"),
+      symbol("="),
+      str_literal("\"Tom\" & Jerry", "&quot;Tom&quot; &amp; Jerry"),
+      symbol(">"),
+      jsx_text_value(
+        "&lt;&#x27;&#39;&nbsp;&copy;&#x1F600; &unknown; & &#xD800;&#32;",
+        Some("<''\u{a0}\u{a9}\u{1F600} &unknown; & &#xD800; "),
+      ),
+      symbol("</"),
+      identifier("a"),
+      symbol(">"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
   #[test]
   fn comments() {
     let lexer = Lexer::from_text(
@@ -341,7 +1250,7 @@ mod lexer_tests {
       /*
       and this
       */
-      <>/* not this */{/* but this */}</>
+      return <>/* not this */{/* but this */}</>
     ",
     );
     let result = vec![
@@ -351,8 +1260,9 @@ mod lexer_tests {
       identifier("not_this"),
       symbol("("),
       symbol(")"),
+      keyword("return"),
       symbol("<>"),
-      s_literal("/* not this */"),
+      jsx_text("/* not this */"),
       symbol("{"),
       symbol("}"),
       symbol("</>"),
@@ -362,6 +1272,248 @@ mod lexer_tests {
     assert_result(lexer, result);
   }
 
+  #[test]
+  fn trivia() {
+    let mut lexer = Lexer::from_text(
+      "/** License */\r\n// @ts-ignore\nfoo( /* a */ 1 ) // end\n\n  return\t\n<>\n</> // end\n\n// eof",
+    )
+    .with_trivia();
+    let tokens: Vec<_> = lexer
+      .by_ref()
+      .map(|t| {
+        let kinds = |trivia: &Vec<_>| {
+          trivia
+            .iter()
+            .map(|t: &Trivia| (t.kind, t.text))
+            .collect::<Vec<_>>()
+        };
+        (
+          t.token.kind,
+          kinds(&t.leading_trivia),
+          kinds(&t.trailing_trivia),
+        )
+      })
+      .collect();
+
+    let trivia = |kind: TriviaKind, text: &'static str| (kind, text);
+    let newline = || trivia(TriviaKind::Newline, "\n");
+    let space = |text: &'static str| trivia(TriviaKind::Whitespace, text);
+    assert_eq!(
+      tokens,
+      vec![
+        (
+          TokenKind::Identifier,
+          vec![
+            trivia(TriviaKind::BlockComment, "/** License */"),
+            trivia(TriviaKind::Newline, "\r\n"),
+            trivia(TriviaKind::LineComment, "// @ts-ignore"),
+            newline(),
+          ],
+          vec![],
+        ),
+        (
+          TokenKind::OpenParen,
+          vec![],
+          vec![
+            space(" "),
+            trivia(TriviaKind::BlockComment, "/* a */"),
+            space(" "),
+          ],
+        ),
+        (TokenKind::NumericLiteral, vec![], vec![space(" ")]),
+        (
+          TokenKind::CloseParen,
+          vec![],
+          vec![
+            space(" "),
+            trivia(TriviaKind::LineComment, "// end"),
+            newline(),
+          ],
+        ),
+        (
+          TokenKind::Return,
+          vec![newline(), space("  ")],
+          vec![space("\t"), newline()],
+        ),
+        (TokenKind::LessThanGreaterThan, vec![], vec![]),
+        (TokenKind::JsxText, vec![], vec![]),
+        (
+          TokenKind::LessThanSlashGreaterThan,
+          vec![],
+          vec![
+            space(" "),
+            trivia(TriviaKind::LineComment, "// end"),
+            newline(),
+          ],
+        ),
+      ]
+    );
+    assert_eq!(
+      lexer
+        .end_of_file_trivia()
+        .iter()
+        .map(|t| (t.kind, t.text))
+        .collect::<Vec<_>>(),
+      vec![newline(), trivia(TriviaKind::LineComment, "// eof")]
+    );
+  }
+
+  #[test]
+  fn trivia_round_trip() {
+    let text = "/**\n * Docs\n */\nexport function  f(a/*:number*/) {\r\n  return `${ a }`; /* tail\n comment */\n}\n";
+    let mut lexer = Lexer::from_text(text).with_trivia();
+    let mut rebuilt = String::new();
+    for token in lexer.by_ref() {
+      let trivia_text = |trivia: &Vec<Trivia>| trivia.iter().map(|t| t.text).collect::<String>();
+      rebuilt.push_str(&trivia_text(&token.leading_trivia));
+      rebuilt.push_str(&text[token.span.start..token.span.end]);
+      rebuilt.push_str(&trivia_text(&token.trailing_trivia));
+    }
+    for trivia in lexer.end_of_file_trivia() {
+      rebuilt.push_str(trivia.text);
+    }
+
+    assert_eq!(rebuilt, text);
+  }
+
+  #[test]
+  fn no_trivia_by_default() {
+    let mut lexer = Lexer::from_text("// comment\na /* b */ c // d");
+
+    assert!(lexer
+      .by_ref()
+      .all(|t| t.leading_trivia.is_empty() && t.trailing_trivia.is_empty()));
+    assert!(lexer.end_of_file_trivia().is_empty());
+  }
+
+  #[test]
+  fn bom_and_shebang() {
+    let lexer = Lexer::from_bytes(b"\xEF\xBB\xBF#!/usr/bin/env node\nfoo #!bar").unwrap();
+    let result = vec![
+      identifier("foo"),
+      symbol("#"),
+      symbol("!"),
+      identifier("bar"),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+
+    let lexer = Lexer::from_text("#!/usr/bin/env node\r\nfoo").with_trivia();
+    let tokens: Vec<_> = lexer.collect();
+    assert_eq!(tokens[0].leading_trivia[0].kind, TriviaKind::Shebang);
+    assert_eq!(tokens[0].leading_trivia[0].text, "#!/usr/bin/env node");
+  }
+
+  #[test]
+  fn line_endings() {
+    let source = read_source(&b"a\r\nb\rc\nd\r\n\r\ne"[..]).unwrap();
+    let lexer = Lexer::from_text(&source);
+    let lines: Vec<_> = lexer.map(|t| (t.line, t.col)).collect();
+
+    assert_eq!(lines, vec![(1, 1), (2, 1), (3, 1), (4, 1), (6, 1)]);
+  }
+
+  #[test]
+  fn invalid_utf8() {
+    let error = Lexer::from_bytes(b"let a = 1;\nlet \xC3\x28 = 2;").err();
+    assert_eq!(
+      error,
+      Some(String::from("line: 2 col: 5 Invalid UTF-8 sequence: [195]"))
+    );
+
+    let error = read_source(&b"\xFF"[..]).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn templates() {
+    let lexer = Lexer::from_text("`plain` `a${b}c${ { d } }e` tag`\\unicode`");
+    let result = vec![
+      template(TokenKind::NoSubstitutionTemplate, "plain"),
+      template(TokenKind::TemplateHead, "a"),
+      identifier("b"),
+      template(TokenKind::TemplateMiddle, "c"),
+      symbol("{"),
+      identifier("d"),
+      symbol("}"),
+      template(TokenKind::TemplateTail, "e"),
+      identifier("tag"),
+      Ok(Token::template(
+        TokenKind::NoSubstitutionTemplate,
+        TemplateChunk {
+          cooked: None,
+          raw: Cow::from("\\unicode"),
+        },
+      )),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn template_text() {
+    let lexer = Lexer::from_text("`  two\r\n lines \\n\\u{1F600}\\x41 $ {}\\`` `\\\n`");
+    let result = vec![
+      Ok(Token::template(
+        TokenKind::NoSubstitutionTemplate,
+        TemplateChunk {
+          cooked: Some(Cow::from("  two\n lines \n😀A $ {}`")),
+          raw: Cow::from("  two\n lines \\n\\u{1F600}\\x41 $ {}\\`"),
+        },
+      )),
+      Ok(Token::template(
+        TokenKind::NoSubstitutionTemplate,
+        TemplateChunk {
+          cooked: Some(Cow::from("")),
+          raw: Cow::from("\\\n"),
+        },
+      )),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn nested_templates() {
+    let lexer = Lexer::from_text("`a${`b${c}`}d${<Elm>{`e`}</Elm>}`");
+    let result = vec![
+      template(TokenKind::TemplateHead, "a"),
+      template(TokenKind::TemplateHead, "b"),
+      identifier("c"),
+      template(TokenKind::TemplateTail, ""),
+      template(TokenKind::TemplateMiddle, "d"),
+      symbol("<"),
+      identifier("Elm"),
+      symbol(">"),
+      symbol("{"),
+      template(TokenKind::NoSubstitutionTemplate, "e"),
+      symbol("}"),
+      symbol("</"),
+      identifier("Elm"),
+      symbol(">"),
+      template(TokenKind::TemplateTail, ""),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
+  #[test]
+  fn unterminated_template() {
+    let lexer = Lexer::from_text("`a${b}c");
+    let result = vec![
+      template(TokenKind::TemplateHead, "a"),
+      identifier("b"),
+      Err(String::from("Unterminated template literal")),
+    ]
+    .into_iter();
+
+    assert_result(lexer, result);
+  }
+
   #[test]
   fn void() {
     let lexer = Lexer::from_text("");
@@ -372,23 +1524,175 @@ mod lexer_tests {
 
   // TODO spread props <Element {...props} /> <Element lol {...props} />
 
-  fn assert_result(lexer: Lexer, result: IntoIter<TokenResult>) {
-    assert_equal(lexer.map(|v| v.token), result);
+  #[test]
+  fn relex_edited_tokens() {
+    let text =
+      "import a from 'a';\nconst b = a + 1; // one\n\nfunction c() {\n  return b\n}\nlet d = 'd'";
+    // Renamed, inserted, deleted, on the first and the last token
+    assert_relex(text, Span::new(25, 26), "bb");
+    assert_relex(text, Span::new(29, 29), "\n  ");
+    assert_relex(text, Span::new(17, 36), "");
+    assert_relex(text, Span::new(0, 6), "export");
+    assert_relex(text, Span::new(80, 83), "'e'");
+    // In a comment, in the trailing trivia of a token
+    assert_relex(text, Span::new(39, 42), "two");
+    // Inside braces and at the end of a token
+    assert_relex(text, Span::new(68, 69), "bc");
+    assert_relex(text, Span::new(26, 26), "c");
+  }
+
+  #[test]
+  fn relex_changes_the_state_of_what_follows() {
+    // The rest of the file becomes a comment, a template, or is a string no more
+    assert_relex("a;\nb = 1;\nc = 2", Span::new(2, 2), "/*");
+    assert_relex("a;\nb = `1`;\nc = 2", Span::new(8, 9), "${");
+    assert_relex("a = 'b\nc = 2", Span::new(6, 6), "'");
+    // Templates and JSX are read again from their start
+    assert_relex("x = `a ${b} c`;\nd", Span::new(9, 10), "bb");
+    assert_relex("x = <a>text</a>;\nd", Span::new(7, 11), "other");
+    // The same `<` is JSX no more
+    assert_relex("let b = <div>hi {a}</div>;", Span::new(5, 7), "");
+    // `<T,>` are type parameters, `<T>` starts JSX
+    assert_relex("const f = <T,>(a) => a;\nb", Span::new(12, 13), "");
+    assert_relex("const f = <T>(a) => a;\nb", Span::new(12, 12), ",");
+    assert_relex(
+      "const f = <T extends U>(a) => a;\nb",
+      Span::new(21, 22),
+      "=\"\"",
+    );
+  }
+
+  #[test]
+  fn relex_matches_lexing_the_whole_text() {
+    let texts = [
+      "let b = <div>hi {a}</div>;\nc = x / 2 / 3;\nd = /re/g.test(`t ${e}`)",
+      "function f<T>(a: T) {\n  return <T,>(b) => a < b;\n}\n'str' // end",
+      "const x = { a: [1, 2], b: `${ {c} }` };\n/* note */ y = x > 1 ? <a b=\"c\" /> : 0",
+    ];
+    let fragments = [
+      "", "=", " =", "<", "</", ">", "/", "{", "}", "(", "`", "'", "\n", "a", "/*", "*/", "<div>",
+      "${", "1",
+    ];
+    // Pseudo-random, but the same edits on every run
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move |n: usize| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed % n as u64) as usize
+    };
+    for text in texts.iter() {
+      for _ in 0..300 {
+        let start = random(text.len() + 1);
+        let end = (start + random(6)).min(text.len());
+        let inserted = fragments[random(fragments.len())];
+        assert_relex(text, Span::new(start, end), inserted);
+      }
+    }
+  }
+
+  #[test]
+  fn relex_reuses_tokens_after_the_edit() {
+    let text = "a = 'x';\nb = 'y'";
+    let new_text = "abc = 'x';\nb = 'y'";
+    let previous: Vec<_> = Lexer::from_text(text).collect();
+    let relexed = Lexer::from_text(new_text).relex(&previous, &TextEdit::new(Span::new(0, 1), 3));
+
+    let raw = |token: &LocatedToken| match &token.token.value {
+      Some(TokenValue::Literal(Literal::Str(_, raw))) => raw.as_ptr(),
+      _ => panic!("Not a string"),
+    };
+    // Read again up to `=`, the first token that is the same, the rest is moved
+    assert_eq!(relexed[0].token, Token::identifier(Atom::new("abc")));
+    assert_eq!(raw(&relexed[2]), text[5..].as_ptr());
+    assert_eq!(relexed[2].span, Span::new(6, 9));
+    assert_eq!(raw(&relexed[6]), text[14..].as_ptr());
+    assert_eq!(relexed[6].span, Span::new(15, 18));
+    assert_eq!((relexed[6].line, relexed[6].col), (2, 5));
   }
 
-  fn keyword(string: &str) -> TokenResult {
-    Ok(Token::Keyword(string.to_string()))
+  fn assert_relex(text: &str, span: Span, inserted: &str) {
+    fn lexer(text: &str, trivia: bool) -> Lexer<'_> {
+      let lexer = Lexer::from_text(text);
+      if trivia {
+        lexer.with_trivia()
+      } else {
+        lexer
+      }
+    }
+    // Every field of the tokens
+    fn located(token: &LocatedToken) -> String {
+      format!("{:?}", token)
+    }
+
+    let new_text = format!("{}{}{}", &text[..span.start], inserted, &text[span.end..]);
+    let edit = TextEdit::new(span, inserted.len());
+    for trivia in [false, true] {
+      let previous: Vec<_> = lexer(text, trivia).collect();
+      let relexed = lexer(&new_text, trivia).relex(&previous, &edit);
+
+      assert_equal(
+        relexed.iter().map(located),
+        lexer(&new_text, trivia).map(|t| located(&t)),
+      );
+    }
+  }
+
+  type TokenResult<'a> = Result<Token<'a>, String>;
+
+  fn token_result(located: LocatedToken) -> TokenResult {
+    located.result().cloned().map_err(|d| d.message.clone())
+  }
+  fn assert_result(lexer: Lexer, expected: IntoIter<TokenResult>) {
+    assert_equal(lexer.map(token_result), expected);
+  }
+
+  fn keyword(string: &str) -> TokenResult<'_> {
+    Ok(Token::new(TokenKind::keyword(string).unwrap()))
+  }
+  fn identifier(string: &str) -> TokenResult<'_> {
+    Ok(Token::identifier(Atom::new(string)))
+  }
+  fn symbol(string: &str) -> TokenResult<'_> {
+    Ok(symbol_token(string))
+  }
+  fn symbol_token(string: &str) -> Token<'_> {
+    Token::new(TokenKind::punctuator(string).unwrap())
+  }
+  fn i_literal(raw: &str) -> TokenResult<'_> {
+    Ok(Token::literal(Literal::Decimal(raw.parse().unwrap(), raw)))
+  }
+  fn d_literal(raw: &str, value: f64) -> TokenResult<'_> {
+    Ok(Token::literal(Literal::Decimal(value, raw)))
+  }
+  fn b_literal(raw: &str) -> TokenResult<'_> {
+    Ok(Token::literal(Literal::BigInt(raw)))
+  }
+  fn template(kind: TokenKind, text: &str) -> TokenResult<'_> {
+    Ok(Token::template(
+      kind,
+      TemplateChunk {
+        cooked: Some(Cow::Borrowed(text)),
+        raw: Cow::Borrowed(text),
+      },
+    ))
+  }
+  fn str_literal<'a>(value: &'a str, raw: &'a str) -> TokenResult<'a> {
+    Ok(Token::literal(Literal::Str(Cow::Borrowed(value), raw)))
   }
-  fn identifier(string: &str) -> TokenResult {
-    Ok(Token::Identifier(string.to_string()))
+  fn regex<'a>(pattern: &'a str, flags: &'a str) -> TokenResult<'a> {
+    Ok(Token::literal(Literal::Regex(pattern, flags)))
   }
-  fn symbol(string: &str) -> TokenResult {
-    Ok(Token::Symbol(string.to_string()))
+  fn jsx_text(raw: &str) -> TokenResult<'_> {
+    jsx_text_value(raw, Some(raw))
   }
-  fn i_literal(value: i32) -> TokenResult {
-    Ok(Token::Literal(Literal::Integer(value)))
+  fn jsx_text_value<'a>(raw: &'a str, value: Option<&'a str>) -> TokenResult<'a> {
+    Ok(Token::jsx_text(JsxText {
+      raw,
+      value: value.map(Cow::Borrowed),
+    }))
   }
-  fn s_literal(string: &str) -> TokenResult {
-    Ok(Token::Literal(Literal::Str(string.to_string())))
+  fn s_literal(string: &str) -> TokenResult<'_> {
+    Ok(Token::literal(Literal::Str(Cow::Borrowed(string), string)))
   }
 }
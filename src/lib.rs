@@ -0,0 +1,16 @@
+mod asi_tests;
+pub mod ast;
+pub mod atom;
+mod atom_tests;
+pub mod cursor;
+mod cursor_tests;
+mod entities;
+mod expression_tests;
+pub mod lexer;
+mod lexer_tests;
+pub mod parser;
+mod parser_tests;
+mod source_file_tests;
+pub mod span;
+mod span_tests;
+pub mod tokens;
@@ -1,25 +1,29 @@
-mod ast;
-mod lexer;
-mod lexer_tests;
-mod parser;
-mod parser_tests;
-mod tokens;
-
-use crate::ast::source_file::SourceFile;
-use crate::lexer::Lexer;
+use oli_script::ast::source_file::SourceFile;
+use oli_script::lexer::{read_source, read_source_file, LanguageVariant, Lexer};
 use std::time::Instant;
+use std::{env, io, process};
 
 fn main() {
   let now = Instant::now();
 
-  // let lexer = Lexer::from_file("./program.tsx").unwrap();
-  let lexer = Lexer::from_text(
-    "
-    import foo, { foo: foo2, bar } from 'react';
-
-    function myFunction<T, K extends string>(value: T, key?: K, option = false) {}
-  ",
-  );
+  // oli-script ./program.tsx, or the source from stdin without a path or with `-`
+  let path = env::args().nth(1).filter(|path| path != "-");
+  let source = match &path {
+    Some(path) => read_source_file(path),
+    None => read_source(io::stdin()),
+  };
+  let source = match source {
+    Ok(source) => source,
+    Err(e) => {
+      eprintln!("{}", e);
+      process::exit(1);
+    }
+  };
+  let variant = path
+    .as_deref()
+    .and_then(LanguageVariant::from_path)
+    .unwrap_or(LanguageVariant::Tsx);
+  let lexer = Lexer::from_text(&source).with_variant(variant);
 
   println!("Opened in {}ms", now.elapsed().as_micros() as f64 / 1000.0);
 
@@ -1,6 +1,10 @@
+use crate::span::{Span, Spanned};
+use crate::{unwrap_branch, unwrap_enum};
 use core::fmt::Debug;
+use std::cell::{OnceCell, RefCell};
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::rc::{Rc, Weak};
 
 /// Global ///
 
@@ -30,6 +34,22 @@ pub enum MatchResultValue<Token> {
   Branch(usize, Box<MatchResultValue<Token>>),  // OneOf
 }
 
+impl<Token: Spanned> MatchResultValue<Token> {
+  /// From the first token matched to the last one, None if nothing was: an empty optional
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      MatchResultValue::Token(token) => Some(token.span()),
+      MatchResultValue::Vector(values) => {
+        let first = values.iter().find_map(|v| v.span())?;
+        let last = values.iter().rev().find_map(|v| v.span())?;
+        Some(first.to(last))
+      }
+      MatchResultValue::Option(value) => value.as_ref().and_then(|v| v.span()),
+      MatchResultValue::Branch(_, value) => value.span(),
+    }
+  }
+}
+
 #[derive(Clone)]
 pub enum MatcherType<Token> {
   OneOf(OneOf<Token>),
@@ -37,6 +57,9 @@ pub enum MatcherType<Token> {
   Loop(Loop<Token>),
   Terminal(Terminal<Token>),
   Optional(Optional<Token>),
+  Rule(Rule<Token>),
+  Label(Label<Token>),
+  Operators(Operators<Token>),
   _Marker(PhantomData<Token>),
 }
 
@@ -48,6 +71,9 @@ impl<Token: Clone + Debug> MatcherType<Token> {
       MatcherType::Loop(v) => v.reset(),
       MatcherType::Terminal(v) => v.reset(),
       MatcherType::Optional(v) => v.reset(),
+      MatcherType::Rule(v) => v.reset(),
+      MatcherType::Label(v) => v.reset(),
+      MatcherType::Operators(v) => v.reset(),
       _ => {}
     }
   }
@@ -58,14 +84,85 @@ impl<Token: Clone + Debug> MatcherType<Token> {
       MatcherType::Loop(v) => v.next(token),
       MatcherType::Terminal(v) => v.next(token),
       MatcherType::Optional(v) => v.next(token),
+      MatcherType::Rule(v) => v.next(token),
+      MatcherType::Label(v) => v.next(token),
+      MatcherType::Operators(v) => v.next(token),
       _ => MatcherResult::Rejected,
     }
   }
+  /// What the next token could be, to call before `next`: once rejected it's forgotten
+  pub fn expected(&self) -> Expected {
+    let mut expected = Expected::default();
+    self.collect_expected(&mut expected);
+    expected
+  }
+  fn collect_expected(&self, expected: &mut Expected) {
+    match self {
+      MatcherType::OneOf(v) => v.collect_expected(expected),
+      MatcherType::Sequence(v) => v.collect_expected(expected),
+      MatcherType::Loop(v) => v.collect_expected(expected),
+      MatcherType::Terminal(v) => v.collect_expected(expected),
+      MatcherType::Optional(v) => v.collect_expected(expected),
+      MatcherType::Rule(v) => v.collect_expected(expected),
+      MatcherType::Label(v) => v.collect_expected(expected),
+      MatcherType::Operators(v) => v.collect_expected(expected),
+      _ => {}
+    }
+  }
+  // What this matcher is called after it matched: rules and labels of more than a token
+  fn name(&self) -> Option<Rc<str>> {
+    match self {
+      MatcherType::Rule(v) => Some(v.with_definition(|d| d.name).into()),
+      MatcherType::Optional(v) => v.matcher.name(),
+      MatcherType::Label(v) if !matches!(*v.matcher, MatcherType::Terminal(_)) => {
+        Some(v.name.clone())
+      }
+      _ => None,
+    }
+  }
+  fn is_optional(&self) -> bool {
+    match self {
+      MatcherType::Optional(_) => true,
+      MatcherType::Label(v) => v.matcher.is_optional(),
+      _ => false,
+    }
+  }
 }
 
 pub trait Matcher<Token> {
   fn next(&mut self, token: &Token) -> MatcherResult<Token>;
   fn reset(&mut self);
+  /// Adds the labels of the live matchers that would take the next token
+  fn collect_expected(&self, expected: &mut Expected);
+}
+
+/**
+ * The tokens a matcher could have taken where it rejected one, by the labels of the matchers
+ * that were waiting for them, expected `,` or `}` after import specifier.
+ * Unlabeled terminals aren't listed, a label or rule that hasn't started is listed instead
+ * of what's inside it.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Expected {
+  pub labels: Vec<Rc<str>>,
+  pub after: Option<Rc<str>>, // The largest rule or label that ended with the last token
+}
+
+impl Expected {
+  fn add(&mut self, label: &Rc<str>) {
+    if !self.labels.contains(label) {
+      self.labels.push(label.clone());
+    }
+  }
+
+  /// `a`, `b` or `c`
+  pub fn one_of(&self) -> String {
+    match self.labels.split_last() {
+      None => String::new(),
+      Some((last, [])) => last.to_string(),
+      Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+  }
 }
 
 /// OneOf ///
@@ -91,7 +188,7 @@ impl<Token: Clone + Debug> Matcher<Token> for OneOf<Token> {
     let length = self.matchers.len();
     for i in 0..length {
       let matcher = &mut self.matchers[i];
-      match (matcher.next(&token), &result) {
+      match (matcher.next(token), &result) {
         (MatcherResult::Accepted, _) => {
           has_accepted = true;
         }
@@ -132,6 +229,11 @@ impl<Token: Clone + Debug> Matcher<Token> for OneOf<Token> {
       matcher.reset();
     }
   }
+  fn collect_expected(&self, expected: &mut Expected) {
+    for matcher in &self.matchers {
+      matcher.collect_expected(expected);
+    }
+  }
 }
 
 /// Sequence ///
@@ -144,6 +246,7 @@ struct SequenceMatcher<Token> {
   matcher: MatcherType<Token>,
   result: Option<MatchResultValue<Token>>,
   is_head: bool,
+  matched_last: bool, // `result` was set by the last token
 }
 
 impl<Token: Clone + Debug> Sequence<Token> {
@@ -155,12 +258,13 @@ impl<Token: Clone + Debug> Sequence<Token> {
           matcher,
           result: None,
           is_head: false,
+          matched_last: false,
         })
         .collect(),
     };
     result.sequence_matchers[0].is_head = true;
     result.propagate_optional_heads();
-    return result;
+    result
   }
 
   pub fn matcher(matchers: Vec<MatcherType<Token>>) -> MatcherType<Token> {
@@ -170,7 +274,7 @@ impl<Token: Clone + Debug> Sequence<Token> {
   fn propagate_optional_heads(&mut self) {
     for i in 1..self.sequence_matchers.len() {
       let prev = &self.sequence_matchers[i - 1];
-      if prev.is_head && matches!(prev.matcher, MatcherType::Optional(_)) {
+      if prev.is_head && prev.matcher.is_optional() {
         self.sequence_matchers[i].is_head = true;
       }
     }
@@ -180,8 +284,9 @@ impl<Token: Clone + Debug> Sequence<Token> {
 impl<Token: Clone + Debug> SequenceMatcher<Token> {
   fn reset(&mut self) {
     self.is_head = false;
+    self.matched_last = false;
     self.matcher.reset();
-    self.result = if matches!(self.matcher, MatcherType::Optional(_)) {
+    self.result = if self.matcher.is_optional() {
       Some(MatchResultValue::Option(None))
     } else {
       None
@@ -197,6 +302,9 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
     }
 
     let mut has_updated = false;
+    for sequence_matcher in &mut self.sequence_matchers {
+      sequence_matcher.matched_last = false;
+    }
     for i in (0..self.sequence_matchers.len()).rev() {
       let sequence_matcher = &mut self.sequence_matchers[i];
       if !sequence_matcher.is_head {
@@ -208,7 +316,7 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
           sequence_matcher.is_head = false;
         }
         MatcherResult::End(r)
-          if matches!(sequence_matcher.matcher, MatcherType::Optional(_))
+          if sequence_matcher.matcher.is_optional()
             && matches!(r, MatchResultValue::Option(None)) =>
         {
           sequence_matcher.is_head = false;
@@ -216,6 +324,7 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
         MatcherResult::End(r) => {
           has_updated = true;
           sequence_matcher.result = Some(r);
+          sequence_matcher.matched_last = true;
           sequence_matcher.is_head = false;
           // We have a new value: Reset all following matchers
           for j in (i + 1)..self.sequence_matchers.len() {
@@ -229,6 +338,7 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
         MatcherResult::Value(v) => {
           has_updated = true;
           sequence_matcher.result = Some(v);
+          sequence_matcher.matched_last = true;
           // Same as before, but keeping this as head.
           for j in (i + 1)..self.sequence_matchers.len() {
             self.sequence_matchers[j].reset();
@@ -244,17 +354,14 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
     self.propagate_optional_heads();
 
     let has_head = self.sequence_matchers.iter().any(|m| m.is_head);
-    let is_complete = !self
-      .sequence_matchers
-      .iter()
-      .any(|m| matches!(m.result, None));
+    let is_complete = !self.sequence_matchers.iter().any(|m| m.result.is_none());
     // println!("has_head: {}, is_complete: {}", has_head, is_complete);
     if is_complete && has_updated {
       let result: MatchResultValue<Token> = MatchResultValue::Vector(
         self
           .sequence_matchers
           .iter()
-          .map(|m| (&m.result).as_ref().unwrap().clone())
+          .map(|m| m.result.as_ref().unwrap().clone())
           .collect(),
       );
       if has_head {
@@ -281,6 +388,22 @@ impl<Token: Clone + Debug> Matcher<Token> for Sequence<Token> {
     self.sequence_matchers[0].is_head = true;
     self.propagate_optional_heads()
   }
+  fn collect_expected(&self, expected: &mut Expected) {
+    for (i, sequence_matcher) in self.sequence_matchers.iter().enumerate() {
+      if !sequence_matcher.is_head {
+        continue;
+      }
+      sequence_matcher.matcher.collect_expected(expected);
+      // The outermost one wins, set after the matchers inside
+      let previous = i.checked_sub(1).map(|i| &self.sequence_matchers[i]);
+      if let Some(name) = previous
+        .filter(|m| m.matched_last)
+        .and_then(|m| m.matcher.name())
+      {
+        expected.after = Some(name);
+      }
+    }
+  }
 }
 
 /// Loop ///
@@ -298,6 +421,7 @@ impl<Token: Clone> Loop<Token> {
         matcher,
         is_head: true,
         result: None,
+        matched_last: false,
       }],
     }
   }
@@ -326,8 +450,9 @@ impl<Token: Clone + Debug> Matcher<Token> for Loop<Token> {
           matcher_state.result = Some(v);
           self.matchers.truncate(i + 1);
           let result = MatcherResult::Value(MatchResultValue::Vector(
-            (&self.matchers)
-              .into_iter()
+            self
+              .matchers
+              .iter()
               .map(|matcher_state| matcher_state.result.as_ref().unwrap().clone())
               .collect(),
           ));
@@ -336,6 +461,7 @@ impl<Token: Clone + Debug> Matcher<Token> for Loop<Token> {
             matcher: self.original.deref().clone(),
             is_head: true,
             result: None,
+            matched_last: false,
           });
 
           return result;
@@ -345,8 +471,9 @@ impl<Token: Clone + Debug> Matcher<Token> for Loop<Token> {
           matcher_state.result = Some(v.clone());
           self.matchers.truncate(i + 1);
           let result = MatcherResult::Value(MatchResultValue::Vector(
-            (&self.matchers)
-              .into_iter()
+            self
+              .matchers
+              .iter()
               .map(|matcher_state| matcher_state.result.as_ref().unwrap().clone())
               .collect(),
           ));
@@ -355,6 +482,7 @@ impl<Token: Clone + Debug> Matcher<Token> for Loop<Token> {
             matcher: self.original.deref().clone(),
             is_head: true,
             result: None,
+            matched_last: false,
           });
 
           return result;
@@ -375,8 +503,14 @@ impl<Token: Clone + Debug> Matcher<Token> for Loop<Token> {
       matcher: self.original.deref().clone(),
       is_head: true,
       result: None,
+      matched_last: false,
     }]
   }
+  fn collect_expected(&self, expected: &mut Expected) {
+    for matcher_state in self.matchers.iter().filter(|m| m.is_head) {
+      matcher_state.matcher.collect_expected(expected);
+    }
+  }
 }
 
 /// Terminal ///
@@ -413,6 +547,8 @@ impl<Token: Clone + Debug> Matcher<Token> for Terminal<Token> {
   fn reset(&mut self) {
     self.executed = false
   }
+  // Only labels describe a terminal, see `Label`
+  fn collect_expected(&self, _: &mut Expected) {}
 }
 
 // impl<'a, Token: Clone + Debug> Matcher<Token> for &'a mut Terminal<Token> {
@@ -470,6 +606,913 @@ impl<Token: Clone + Debug> Matcher<Token> for Optional<Token> {
     self.matcher.reset();
     self.has_emitted = false;
   }
+  fn collect_expected(&self, expected: &mut Expected) {
+    self.matcher.collect_expected(expected);
+  }
+}
+
+/// Operators ///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+  Left,  // a - b - c is (a - b) - c
+  Right, // a ** b ** c is a ** (b ** c)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixity {
+  Prefix,
+  Infix(Associativity),
+  Postfix, // Applied to what's on its left at its precedence too: a < b as T is (a < b) as T
+}
+
+/// An operator of `Operators`, what it matches can be more than a token: `? a :`, `(a, b)`
+#[derive(Clone)]
+pub struct Operator<Token> {
+  fixity: Fixity,
+  precedence: u8, // Higher binds tighter
+  matcher: MatcherType<Token>,
+  // Precedences of the operations that can't be its operands without parentheses
+  excluded_left: Vec<u8>,
+  excluded_right: Vec<u8>,
+}
+
+impl<Token> Operator<Token> {
+  pub fn new(fixity: Fixity, precedence: u8, matcher: MatcherType<Token>) -> Self {
+    Operator {
+      fixity,
+      precedence,
+      matcher,
+      excluded_left: vec![],
+      excluded_right: vec![],
+    }
+  }
+
+  /// Its left operand can't be an operation at `precedence` without parentheses: -a ** b
+  pub fn excluding_left(mut self, precedence: u8) -> Self {
+    self.excluded_left.push(precedence);
+    self
+  }
+
+  /// Neither of its operands can: a ?? b || c
+  pub fn excluding(mut self, precedence: u8) -> Self {
+    self.excluded_left.push(precedence);
+    self.excluded_right.push(precedence);
+    self
+  }
+}
+
+/**
+ * Operands joined by prefix, infix and postfix operators, by precedence and associativity,
+ * instead of a rule for each level of precedence. A shunting-yard read one token at a time:
+ * operands wait on a stack until an operator that binds less tightly comes.
+ *
+ * The value is a tree of branches: `Branch(0, operand)` for an operand, and for the operator
+ * `i`, `Branch(i + 1, Vector([operator, operand]))` when prefix,
+ * `Vector([left, operator, right])` when infix and `Vector([operand, operator])` when postfix.
+ *
+ * A token can be read in several ways, `await` is an identifier or the start of
+ * `await promise`: each way is a state of its own, the ones that reject are dropped.
+ * What matched but can go on does, like TypeScript: `a as Array<T>` is never `(a as Array) < T`,
+ * the shorter match only reads the next token if the longer one rejects it.
+ */
+#[derive(Clone)]
+pub struct Operators<Token> {
+  operand: Box<MatcherType<Token>>,
+  operators: Vec<Operator<Token>>,
+  states: Vec<OperatorsState<Token>>,
+}
+
+#[derive(Clone)]
+struct OperatorsState<Token> {
+  operands: Vec<MatchResultValue<Token>>,
+  pending: Vec<(usize, MatchResultValue<Token>)>, // Prefix and infix operators without their right operand
+  expects_operand: bool,
+  candidates: Vec<(usize, MatcherType<Token>)>, // What can come next, by branch: 0 for the operand
+  shorter: Vec<(usize, OperatorsState<Token>)>, // Past a candidate that matched but goes on, by branch
+}
+
+impl<Token: Clone + Debug> Operators<Token> {
+  pub fn new(operand: MatcherType<Token>, operators: Vec<Operator<Token>>) -> Self {
+    let mut result = Operators {
+      operand: Box::new(operand),
+      operators,
+      states: vec![],
+    };
+    result.reset();
+    result
+  }
+  pub fn matcher(
+    operand: MatcherType<Token>,
+    operators: Vec<Operator<Token>>,
+  ) -> MatcherType<Token> {
+    MatcherType::Operators(Operators::new(operand, operators))
+  }
+
+  fn candidates(&self, expects_operand: bool) -> Vec<(usize, MatcherType<Token>)> {
+    let mut candidates = vec![];
+    if expects_operand {
+      candidates.push((0, self.operand.deref().clone()));
+    }
+    for (i, operator) in self.operators.iter().enumerate() {
+      if (operator.fixity == Fixity::Prefix) == expects_operand {
+        candidates.push((i + 1, operator.matcher.clone()));
+      }
+    }
+    candidates
+  }
+
+  // Moves the state past a candidate that matched, false if it can't be read there
+  fn commit(
+    &self,
+    state: &mut OperatorsState<Token>,
+    branch: usize,
+    value: MatchResultValue<Token>,
+  ) -> bool {
+    if branch == 0 {
+      state
+        .operands
+        .push(MatchResultValue::Branch(0, Box::new(value)));
+      state.expects_operand = false;
+    } else {
+      let operator = &self.operators[branch - 1];
+      match operator.fixity {
+        Fixity::Prefix => state.pending.push((branch - 1, value)),
+        Fixity::Infix(associativity) => {
+          self.reduce(state, |top| {
+            top > operator.precedence
+              || (top == operator.precedence && associativity == Associativity::Left)
+          });
+          if !self.can_be_operand(state, operator) {
+            return false;
+          }
+          state.pending.push((branch - 1, value));
+          state.expects_operand = true;
+        }
+        Fixity::Postfix => {
+          self.reduce(state, |top| top >= operator.precedence);
+          if !self.can_be_operand(state, operator) {
+            return false;
+          }
+          let operand = state.operands.pop().unwrap();
+          state.operands.push(MatchResultValue::Branch(
+            branch,
+            Box::new(MatchResultValue::Vector(vec![operand, value])),
+          ));
+        }
+      }
+    }
+    state.candidates = self.candidates(state.expects_operand);
+    true
+  }
+
+  // Whether the operand on its left can be the one of `operator`,
+  // and the operation of `operator` the right operand of the pending one
+  fn can_be_operand(&self, state: &OperatorsState<Token>, operator: &Operator<Token>) -> bool {
+    let left = match state.operands.last() {
+      Some(MatchResultValue::Branch(i, _)) if *i > 0 => Some(self.operators[i - 1].precedence),
+      _ => None,
+    };
+    let excluded_left = left.is_some_and(|left| operator.excluded_left.contains(&left));
+    let excluded_right = match state.pending.last() {
+      Some((i, _)) => self.operators[*i]
+        .excluded_right
+        .contains(&operator.precedence),
+      None => false,
+    };
+    !excluded_left && !excluded_right
+  }
+
+  // Applies the pending operators to their operands while `binds_tighter` their precedence
+  fn reduce(&self, state: &mut OperatorsState<Token>, binds_tighter: impl Fn(u8) -> bool) {
+    while let Some((i, _)) = state.pending.last() {
+      let operator = &self.operators[*i];
+      if !binds_tighter(operator.precedence) {
+        break;
+      }
+      let (i, value) = state.pending.pop().unwrap();
+      let right = state.operands.pop().unwrap();
+      let values = if operator.fixity == Fixity::Prefix {
+        vec![value, right]
+      } else {
+        vec![state.operands.pop().unwrap(), value, right]
+      };
+      state.operands.push(MatchResultValue::Branch(
+        i + 1,
+        Box::new(MatchResultValue::Vector(values)),
+      ));
+    }
+  }
+
+  // The expression read so far, if it's complete
+  fn value(&self, state: &OperatorsState<Token>) -> Option<MatchResultValue<Token>> {
+    if state.expects_operand {
+      return None;
+    }
+    let mut state = state.clone();
+    self.reduce(&mut state, |_| true);
+    state.operands.pop()
+  }
+}
+
+impl<Token: Clone + Debug> Matcher<Token> for Operators<Token> {
+  fn next(&mut self, token: &Token) -> MatcherResult<Token> {
+    let mut result = None;
+    let mut states = vec![];
+    let mut unread = std::mem::take(&mut self.states);
+    unread.reverse();
+
+    while let Some(mut state) = unread.pop() {
+      let mut matched = vec![];
+      let mut going_on = vec![];
+      state
+        .candidates
+        .retain_mut(|(branch, matcher)| match matcher.next(token) {
+          MatcherResult::Rejected => false,
+          MatcherResult::Accepted => {
+            going_on.push(*branch);
+            true
+          }
+          MatcherResult::Value(v) => {
+            going_on.push(*branch);
+            matched.push((*branch, v, true));
+            true
+          }
+          MatcherResult::End(v) => {
+            going_on.push(*branch);
+            matched.push((*branch, v, false));
+            false
+          }
+        });
+
+      // The shorter matches whose candidate doesn't go on with the token read it instead
+      for (branch, shorter) in std::mem::take(&mut state.shorter) {
+        if !going_on.contains(&branch) {
+          unread.push(shorter);
+        }
+      }
+
+      for (branch, value, goes_on) in matched {
+        let mut next_state = OperatorsState {
+          operands: state.operands.clone(),
+          pending: state.pending.clone(),
+          expects_operand: state.expects_operand,
+          candidates: vec![],
+          shorter: vec![],
+        };
+        if !self.commit(&mut next_state, branch, value) {
+          continue;
+        }
+        if result.is_none() {
+          result = self.value(&next_state);
+        }
+        if goes_on {
+          state.shorter.push((branch, next_state));
+        } else if !next_state.candidates.is_empty() {
+          states.push(next_state);
+        }
+      }
+      if !state.candidates.is_empty() {
+        states.push(state);
+      }
+    }
+    self.states = states;
+
+    match (result, self.states.is_empty()) {
+      (Some(value), false) => MatcherResult::Value(value),
+      (Some(value), true) => MatcherResult::End(value),
+      (None, false) => MatcherResult::Accepted,
+      (None, true) => MatcherResult::Rejected,
+    }
+  }
+  fn reset(&mut self) {
+    self.states = vec![OperatorsState {
+      operands: vec![],
+      pending: vec![],
+      expects_operand: true,
+      candidates: self.candidates(true),
+      shorter: vec![],
+    }];
+  }
+  fn collect_expected(&self, expected: &mut Expected) {
+    let mut states: Vec<_> = self.states.iter().collect();
+    while let Some(state) = states.pop() {
+      for (_, matcher) in &state.candidates {
+        matcher.collect_expected(expected);
+      }
+      states.extend(state.shorter.iter().map(|(_, shorter)| shorter));
+    }
+  }
+}
+
+/// Rule ///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RuleId(usize);
+
+struct RuleDefinition<Token> {
+  name: &'static str,
+  matcher: MatcherType<Token>,
+}
+
+// Set once the grammar is built, the matchers of the rules only keep a weak reference to it
+type RuleTable<Token> = OnceCell<Vec<RuleDefinition<Token>>>;
+
+/**
+ * Matches a rule of a grammar, so rules can refer to each other and to themselves.
+ * The matcher of the rule is only made when the first token comes:
+ * `Array<Promise<T>>` makes a new one for each level of nesting.
+ */
+#[derive(Clone)]
+pub struct Rule<Token> {
+  id: RuleId,
+  rules: Weak<RuleTable<Token>>,
+  matcher: Option<Box<MatcherType<Token>>>,
+}
+
+impl<Token> Rule<Token> {
+  fn with_definition<R>(&self, f: impl FnOnce(&RuleDefinition<Token>) -> R) -> R {
+    let rules = self
+      .rules
+      .upgrade()
+      .expect("The grammar was dropped before its matchers");
+    f(&rules.get().expect("The grammar wasn't built")[self.id.0])
+  }
+}
+
+impl<Token: Clone + Debug> Matcher<Token> for Rule<Token> {
+  fn next(&mut self, token: &Token) -> MatcherResult<Token> {
+    if self.matcher.is_none() {
+      self.matcher = Some(Box::new(self.with_definition(|d| d.matcher.clone())));
+    }
+    self.matcher.as_mut().unwrap().next(token)
+  }
+  fn reset(&mut self) {
+    self.matcher = None;
+  }
+  fn collect_expected(&self, expected: &mut Expected) {
+    match &self.matcher {
+      Some(matcher) => matcher.collect_expected(expected),
+      None => expected.add(&self.with_definition(|d| d.name).into()),
+    }
+  }
+}
+
+/// Label ///
+/**
+ * Names a matcher in errors: `expected type`, or `after import specifier` once it matched.
+ * Terminals are only listed in errors with a label.
+ */
+#[derive(Clone)]
+pub struct Label<Token> {
+  name: Rc<str>,
+  matcher: Box<MatcherType<Token>>,
+  started: bool,
+}
+
+impl<Token: Clone + Debug> Label<Token> {
+  pub fn new(name: &str, matcher: MatcherType<Token>) -> Self {
+    Self {
+      name: name.into(),
+      matcher: Box::new(matcher),
+      started: false,
+    }
+  }
+  pub fn matcher(name: &str, matcher: MatcherType<Token>) -> MatcherType<Token> {
+    MatcherType::Label(Label::new(name, matcher))
+  }
+}
+
+impl<Token: Clone + Debug> Matcher<Token> for Label<Token> {
+  fn next(&mut self, token: &Token) -> MatcherResult<Token> {
+    self.started = true;
+    self.matcher.next(token)
+  }
+  fn reset(&mut self) {
+    self.started = false;
+    self.matcher.reset();
+  }
+  fn collect_expected(&self, expected: &mut Expected) {
+    if self.started {
+      self.matcher.collect_expected(expected);
+    } else {
+      expected.add(&self.name);
+    }
+  }
+}
+
+/**
+ * Named rules that can refer to each other, built with `GrammarBuilder`:
+ * let mut builder = Grammar::builder();
+ * let list = builder.declare("list");
+ * builder.define(list, Sequence::matcher(vec![open, Optional::matcher(builder.rule(list)), close]));
+ * let grammar = builder.build()?;
+ * let mut parser = grammar.matcher(list);
+ *
+ * The grammar must outlive the matchers made from it.
+ * Typed rules (`declare_typed`) build a value, their actions are kept by the grammar.
+ */
+pub struct Grammar<'a, Token> {
+  rules: Rc<RuleTable<Token>>,
+  _actions: Vec<Rc<dyn Erased + 'a>>,
+}
+
+pub struct GrammarBuilder<'a, Token> {
+  rules: Rc<RuleTable<Token>>,
+  definitions: Vec<(&'static str, Option<MatcherType<Token>>)>,
+  actions: Vec<Rc<dyn Erased + 'a>>,
+}
+
+// Only to keep the actions of the typed rules, whatever their type
+trait Erased {}
+impl<T: ?Sized> Erased for T {}
+
+type RuleAction<'a, Token, Out> = RefCell<Option<Action<'a, Token, Out>>>;
+
+/// A rule building an `Out`, the typed version of `RuleId`
+pub struct TypedRule<'a, Token, Out> {
+  id: RuleId,
+  action: Weak<RuleAction<'a, Token, Out>>, // Owned by the grammar, rules refer to themselves
+}
+
+impl<'a, Token, Out> Clone for TypedRule<'a, Token, Out> {
+  fn clone(&self) -> Self {
+    TypedRule {
+      id: self.id,
+      action: self.action.clone(),
+    }
+  }
+}
+
+impl<'a, Token: Clone + Debug + 'a> Grammar<'a, Token> {
+  pub fn builder() -> GrammarBuilder<'a, Token> {
+    GrammarBuilder {
+      rules: Rc::new(OnceCell::new()),
+      definitions: vec![],
+      actions: vec![],
+    }
+  }
+
+  pub fn matcher(&self, id: RuleId) -> MatcherType<Token> {
+    rule_matcher(&self.rules, id)
+  }
+
+  pub fn typed_matcher<Out: 'a>(
+    &self,
+    rule: &TypedRule<'a, Token, Out>,
+  ) -> TypedMatcher<'a, Token, Out> {
+    typed_rule_matcher(&self.rules, rule)
+  }
+}
+
+fn typed_rule_matcher<'a, Token: Clone + Debug + 'a, Out: 'a>(
+  rules: &Rc<RuleTable<Token>>,
+  rule: &TypedRule<'a, Token, Out>,
+) -> TypedMatcher<'a, Token, Out> {
+  let action = rule.action.clone();
+  TypedMatcher::new(rule_matcher(rules, rule.id), move |value| {
+    let action = action
+      .upgrade()
+      .expect("The grammar was dropped before its matchers");
+    let action = action.borrow().clone().expect("The rule wasn't defined");
+    action(value)
+  })
+}
+
+fn rule_matcher<Token>(rules: &Rc<RuleTable<Token>>, id: RuleId) -> MatcherType<Token> {
+  MatcherType::Rule(Rule {
+    id,
+    rules: Rc::downgrade(rules),
+    matcher: None,
+  })
+}
+
+impl<'a, Token: Clone + Debug + 'a> GrammarBuilder<'a, Token> {
+  /// A new rule, it can be referred to before it's defined
+  pub fn declare(&mut self, name: &'static str) -> RuleId {
+    self.definitions.push((name, None));
+    RuleId(self.definitions.len() - 1)
+  }
+
+  pub fn define(&mut self, id: RuleId, matcher: MatcherType<Token>) {
+    self.definitions[id.0].1 = Some(matcher);
+  }
+
+  /// Matches the rule `id` where it's used in another matcher
+  pub fn rule(&self, id: RuleId) -> MatcherType<Token> {
+    rule_matcher(&self.rules, id)
+  }
+
+  pub fn declare_typed<Out: 'a>(&mut self, name: &'static str) -> TypedRule<'a, Token, Out> {
+    let action: Rc<RuleAction<'a, Token, Out>> = Rc::new(RefCell::new(None));
+    let rule = TypedRule {
+      id: self.declare(name),
+      action: Rc::downgrade(&action),
+    };
+    self.actions.push(action);
+    rule
+  }
+
+  pub fn define_typed<Out: 'a>(
+    &mut self,
+    rule: &TypedRule<'a, Token, Out>,
+    matcher: TypedMatcher<'a, Token, Out>,
+  ) {
+    let action = rule.action.upgrade().expect("Rule of another grammar");
+    *action.borrow_mut() = Some(matcher.action);
+    self.define(rule.id, matcher.matcher);
+  }
+
+  pub fn typed_rule<Out: 'a>(
+    &self,
+    rule: &TypedRule<'a, Token, Out>,
+  ) -> TypedMatcher<'a, Token, Out> {
+    typed_rule_matcher(&self.rules, rule)
+  }
+
+  /**
+   * Every rule must be defined and match at least one token, an optional rule is written
+   * `Optional::matcher(builder.rule(id))`.
+   * Left recursion is an error: a rule would make a copy of itself for every token
+   * without consuming any, `expression: expression + term`.
+   */
+  pub fn build(self) -> Result<Grammar<'a, Token>, String> {
+    let mut rules = vec![];
+    for (name, matcher) in self.definitions {
+      match matcher {
+        Some(matcher) if can_be_empty(&matcher) => {
+          return Err(format!("Rule {} can match no tokens", name))
+        }
+        Some(matcher) => rules.push(RuleDefinition { name, matcher }),
+        None => return Err(format!("Rule {} is declared but not defined", name)),
+      }
+    }
+    if let Some(cycle) = find_left_recursion(&rules) {
+      let path: Vec<_> = cycle.iter().map(|id| rules[id.0].name).collect();
+      return Err(format!(
+        "Rule {} is left recursive: {}",
+        path[0],
+        path.join(" -> ")
+      ));
+    }
+
+    let _ = self.rules.set(rules);
+    Ok(Grammar {
+      rules: self.rules,
+      _actions: self.actions,
+    })
+  }
+}
+
+/// Rules can't be empty, that's checked for each of them
+fn can_be_empty<Token>(matcher: &MatcherType<Token>) -> bool {
+  match matcher {
+    MatcherType::OneOf(v) => v.matchers.iter().any(can_be_empty),
+    MatcherType::Sequence(v) => v.sequence_matchers.iter().all(|m| can_be_empty(&m.matcher)),
+    MatcherType::Loop(v) => can_be_empty(&v.original),
+    MatcherType::Optional(_) => true,
+    MatcherType::Label(v) => can_be_empty(&v.matcher),
+    MatcherType::Operators(v) => can_be_empty(&v.operand),
+    MatcherType::Terminal(_) | MatcherType::Rule(_) | MatcherType::_Marker(_) => false,
+  }
+}
+
+/// Rules that `matcher` can start with, before reading any token
+fn leftmost_rules<Token>(matcher: &MatcherType<Token>, rules: &mut Vec<RuleId>) {
+  match matcher {
+    MatcherType::OneOf(v) => v.matchers.iter().for_each(|m| leftmost_rules(m, rules)),
+    MatcherType::Sequence(v) => {
+      for sequence_matcher in &v.sequence_matchers {
+        leftmost_rules(&sequence_matcher.matcher, rules);
+        if !can_be_empty(&sequence_matcher.matcher) {
+          break;
+        }
+      }
+    }
+    MatcherType::Loop(v) => leftmost_rules(&v.original, rules),
+    MatcherType::Optional(v) => leftmost_rules(&v.matcher, rules),
+    MatcherType::Rule(v) => rules.push(v.id),
+    MatcherType::Label(v) => leftmost_rules(&v.matcher, rules),
+    MatcherType::Operators(v) => {
+      leftmost_rules(&v.operand, rules);
+      v.operators
+        .iter()
+        .filter(|o| o.fixity == Fixity::Prefix)
+        .for_each(|o| leftmost_rules(&o.matcher, rules));
+    }
+    MatcherType::Terminal(_) | MatcherType::_Marker(_) => {}
+  }
+}
+
+/// The rules of a cycle of leftmost rules, the first one repeated at the end: a -> b -> a
+fn find_left_recursion<Token>(rules: &[RuleDefinition<Token>]) -> Option<Vec<RuleId>> {
+  let edges: Vec<Vec<RuleId>> = rules
+    .iter()
+    .map(|rule| {
+      let mut leftmost = vec![];
+      leftmost_rules(&rule.matcher, &mut leftmost);
+      leftmost
+    })
+    .collect();
+
+  fn visit(
+    id: RuleId,
+    edges: &[Vec<RuleId>],
+    done: &mut Vec<bool>,
+    path: &mut Vec<RuleId>,
+  ) -> Option<Vec<RuleId>> {
+    if let Some(start) = path.iter().position(|&r| r == id) {
+      let mut cycle = path[start..].to_vec();
+      cycle.push(id);
+      return Some(cycle);
+    }
+    if done[id.0] {
+      return None;
+    }
+    path.push(id);
+    for &next in &edges[id.0] {
+      if let Some(cycle) = visit(next, edges, done, path) {
+        return Some(cycle);
+      }
+    }
+    path.pop();
+    done[id.0] = true;
+    None
+  }
+
+  let mut done = vec![false; rules.len()];
+  (0..rules.len()).find_map(|i| visit(RuleId(i), &edges, &mut done, &mut vec![]))
+}
+
+/// Typed ///
+type Action<'a, Token, Out> = Rc<dyn Fn(&MatchResultValue<Token>) -> Out + 'a>;
+
+/**
+ * A matcher that builds a value from what it matched, instead of a `MatchResultValue` tree to
+ * unwrap by position: each combinator reads its own part of the tree.
+ * sequence!(name, annotation.optional()).map(|(name, annotation)| Parameter { name, annotation })
+ * Matching is the same as the untyped matcher's, the value is only built with `value` once it ends.
+ */
+pub struct TypedMatcher<'a, Token, Out> {
+  matcher: MatcherType<Token>,
+  action: Action<'a, Token, Out>,
+}
+
+impl<'a, Token: Clone, Out> Clone for TypedMatcher<'a, Token, Out> {
+  fn clone(&self) -> Self {
+    TypedMatcher {
+      matcher: self.matcher.clone(),
+      action: self.action.clone(),
+    }
+  }
+}
+
+impl<'a, Token: Clone + Debug + 'a> TypedMatcher<'a, Token, Token> {
+  pub fn terminal(match_fn: fn(&Token) -> bool) -> Self {
+    TypedMatcher::new(Terminal::matcher(match_fn), |value| {
+      unwrap_enum!(value, MatchResultValue::Token).clone()
+    })
+  }
+}
+
+impl<'a, Token: Clone + Debug + 'a, Out: 'a> TypedMatcher<'a, Token, Out> {
+  /// An untyped matcher, and how to build the value from its result
+  pub fn new(
+    matcher: MatcherType<Token>,
+    action: impl Fn(&MatchResultValue<Token>) -> Out + 'a,
+  ) -> Self {
+    TypedMatcher {
+      matcher,
+      action: Rc::new(action),
+    }
+  }
+
+  /// A `Sequence`, `action` gets the result of each matcher. See `sequence!` to get a tuple.
+  pub fn sequence(
+    matchers: Vec<MatcherType<Token>>,
+    action: impl Fn(&[MatchResultValue<Token>]) -> Out + 'a,
+  ) -> Self {
+    TypedMatcher::new(Sequence::matcher(matchers), move |value| {
+      action(unwrap_enum!(value, MatchResultValue::Vector))
+    })
+  }
+
+  /// `OneOf`, the value of the branch that matched
+  pub fn one_of(matchers: Vec<Self>) -> Self {
+    let untyped = matchers.iter().map(|m| m.matcher.clone()).collect();
+    let actions: Vec<_> = matchers.into_iter().map(|m| m.action).collect();
+    TypedMatcher::new(OneOf::matcher(untyped), move |value| {
+      let (branch, value) = unwrap_branch!(value);
+      actions[*branch](value)
+    })
+  }
+
+  /// The semantic action: turns the value of this matcher into another one
+  pub fn map<B: 'a>(self, f: impl Fn(Out) -> B + 'a) -> TypedMatcher<'a, Token, B> {
+    let action = self.action;
+    TypedMatcher::new(self.matcher, move |value| f(action(value)))
+  }
+
+  pub fn optional(self) -> TypedMatcher<'a, Token, Option<Out>> {
+    let action = self.action;
+    TypedMatcher::new(Optional::matcher(self.matcher), move |value| {
+      unwrap_enum!(value, MatchResultValue::Option)
+        .as_ref()
+        .map(|v| action(v))
+    })
+  }
+
+  /// `Loop`, one or more times
+  pub fn repeat(self) -> TypedMatcher<'a, Token, Vec<Out>> {
+    let action = self.action;
+    TypedMatcher::new(Loop::matcher(self.matcher), move |value| {
+      unwrap_enum!(value, MatchResultValue::Vector)
+        .iter()
+        .map(|v| action(v))
+        .collect()
+    })
+  }
+
+  /// Names this matcher in errors, see `Label`
+  pub fn label(self, name: &str) -> Self {
+    TypedMatcher {
+      matcher: Label::matcher(name, self.matcher),
+      action: self.action,
+    }
+  }
+
+  pub fn untyped(&self) -> MatcherType<Token> {
+    self.matcher.clone()
+  }
+
+  /// Builds the value from the result this matcher ended with
+  pub fn value(&self, value: &MatchResultValue<Token>) -> Out {
+    (self.action)(value)
+  }
+
+  pub fn next(&mut self, token: &Token) -> MatcherResult<Token> {
+    self.matcher.next(token)
+  }
+
+  pub fn expected(&self) -> Expected {
+    self.matcher.expected()
+  }
+
+  pub fn reset(&mut self) {
+    self.matcher.reset()
+  }
+}
+
+impl<'a, Token: Clone + Debug + Spanned + 'a, Out: 'a> TypedMatcher<'a, Token, Out> {
+  /// `map`, with the span of all the tokens matched, for the AST nodes
+  pub fn map_with_span<B: 'a>(self, f: impl Fn(Out, Span) -> B + 'a) -> TypedMatcher<'a, Token, B> {
+    let action = self.action;
+    TypedMatcher::new(self.matcher, move |value| {
+      f(action(value), value.span().unwrap_or_default())
+    })
+  }
+}
+
+// Builds the value of an operator, `operand` builds the values of its operands
+type OperatorAction<'a, Token, Out> =
+  Rc<dyn Fn(&[MatchResultValue<Token>], &dyn Fn(&MatchResultValue<Token>) -> Out) -> Out + 'a>;
+
+/**
+ * Typed `Operators`, each operator says how to build the value of its operation:
+ * TypedOperators::new(number)
+ *   .infix(1, Associativity::Left, plus, |left, _, right| left + right)
+ *   .prefix(2, minus, |_, operand| -operand)
+ *   .matcher()
+ */
+pub struct TypedOperators<'a, Token, Out> {
+  operand: TypedMatcher<'a, Token, Out>,
+  operators: Vec<Operator<Token>>,
+  actions: Vec<OperatorAction<'a, Token, Out>>,
+}
+
+impl<'a, Token: Clone + Debug + 'a, Out: 'a> TypedOperators<'a, Token, Out> {
+  pub fn new(operand: TypedMatcher<'a, Token, Out>) -> Self {
+    TypedOperators {
+      operand,
+      operators: vec![],
+      actions: vec![],
+    }
+  }
+
+  pub fn prefix<O: 'a>(
+    self,
+    precedence: u8,
+    operator: TypedMatcher<'a, Token, O>,
+    build: impl Fn(O, Out) -> Out + 'a,
+  ) -> Self {
+    let op = operator.action.clone();
+    self.operator(
+      Fixity::Prefix,
+      precedence,
+      operator,
+      move |values, operand| build(op(&values[0]), operand(&values[1])),
+    )
+  }
+
+  pub fn infix<O: 'a>(
+    self,
+    precedence: u8,
+    associativity: Associativity,
+    operator: TypedMatcher<'a, Token, O>,
+    build: impl Fn(Out, O, Out) -> Out + 'a,
+  ) -> Self {
+    let op = operator.action.clone();
+    let fixity = Fixity::Infix(associativity);
+    self.operator(fixity, precedence, operator, move |values, operand| {
+      build(operand(&values[0]), op(&values[1]), operand(&values[2]))
+    })
+  }
+
+  pub fn postfix<O: 'a>(
+    self,
+    precedence: u8,
+    operator: TypedMatcher<'a, Token, O>,
+    build: impl Fn(Out, O) -> Out + 'a,
+  ) -> Self {
+    let op = operator.action.clone();
+    self.operator(
+      Fixity::Postfix,
+      precedence,
+      operator,
+      move |values, operand| build(operand(&values[0]), op(&values[1])),
+    )
+  }
+
+  fn operator<O>(
+    mut self,
+    fixity: Fixity,
+    precedence: u8,
+    operator: TypedMatcher<'a, Token, O>,
+    action: impl Fn(&[MatchResultValue<Token>], &dyn Fn(&MatchResultValue<Token>) -> Out) -> Out + 'a,
+  ) -> Self {
+    self
+      .operators
+      .push(Operator::new(fixity, precedence, operator.matcher));
+    self.actions.push(Rc::new(action));
+    self
+  }
+
+  /// The last operator can't take an operation at `precedence` as its left operand: -a ** b
+  pub fn excluding_left(mut self, precedence: u8) -> Self {
+    let operator = self.operators.pop().unwrap();
+    self.operators.push(operator.excluding_left(precedence));
+    self
+  }
+
+  /// Nor as either of its operands: a ?? b || c
+  pub fn excluding(mut self, precedence: u8) -> Self {
+    let operator = self.operators.pop().unwrap();
+    self.operators.push(operator.excluding(precedence));
+    self
+  }
+
+  pub fn matcher(self) -> TypedMatcher<'a, Token, Out> {
+    let operand = self.operand.action;
+    let actions = self.actions;
+    let matcher = Operators::matcher(self.operand.matcher, self.operators);
+    TypedMatcher::new(matcher, move |value| {
+      operation_value(&operand, &actions, value)
+    })
+  }
+}
+
+fn operation_value<'a, Token, Out>(
+  operand: &Action<'a, Token, Out>,
+  actions: &[OperatorAction<'a, Token, Out>],
+  value: &MatchResultValue<Token>,
+) -> Out {
+  match unwrap_branch!(value) {
+    (0, value) => operand(value),
+    (i, value) => actions[i - 1](
+      unwrap_enum!(value.deref(), MatchResultValue::Vector),
+      &|v| operation_value(operand, actions, v),
+    ),
+  }
+}
+
+/**
+ * A typed `Sequence` of typed matchers, its value is the tuple of their values:
+ * sequence!(open, expression, close).map(|(_, expression, _)| expression)
+ */
+#[macro_export]
+macro_rules! sequence {
+  // Binds each matcher to a name taken from the pool, the names are only known by the macro
+  (@bind [$($name:ident = $bound:expr;)*] [$next:ident $($pool:ident)*] $matcher:expr $(, $rest:expr)*) => {
+    $crate::sequence!(@bind [$($name = $bound;)* $next = $matcher;] [$($pool)*] $($rest),*)
+  };
+  (@bind [$($name:ident = $bound:expr;)*] [$($pool:ident)*]) => {{
+    $(let $name = $bound;)*
+    $crate::parser::TypedMatcher::sequence(vec![$($name.untyped()),*], move |values| {
+      let mut values = values.iter();
+      ($($name.value(values.next().unwrap()),)*)
+    })
+  }};
+  ( $($matcher:expr),+ $(,)? ) => {
+    $crate::sequence!(@bind [] [m0 m1 m2 m3 m4 m5 m6 m7 m8 m9 m10 m11] $($matcher),+)
+  };
 }
 
 #[macro_export]
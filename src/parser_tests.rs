@@ -1,9 +1,13 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod parser_tests {
   // Note this useful idiom: importing names from outer (for mod tests) scope.
   use crate::parser::{
-    Loop, MatchResultValue, MatcherResult, MatcherType, OneOf, Optional, Sequence, Terminal,
+    Associativity, Fixity, Grammar, Label, Loop, MatchResultValue, MatcherResult, MatcherType,
+    OneOf, Operator, Operators, Optional, Sequence, Terminal, TypedMatcher, TypedOperators,
   };
+  use crate::sequence;
+  use crate::span::{Span, Spanned};
 
   /// Terminal
   #[test]
@@ -547,6 +551,443 @@ mod parser_tests {
     );
   }
 
+  /// Rule
+  #[test]
+  fn rule_refers_to_itself() {
+    let mut builder = Grammar::builder();
+    let group = builder.declare("group");
+    builder.define(
+      group,
+      Sequence::matcher(vec![
+        Terminal::matcher(|token: &char| *token == '('),
+        Optional::matcher(builder.rule(group)),
+        Terminal::matcher(|token: &char| *token == ')'),
+      ]),
+    );
+    let grammar = builder.build().unwrap();
+    let mut parser = grammar.matcher(group);
+
+    run_test(
+      &mut parser,
+      "(())",
+      vec![
+        MatcherResult::Accepted,
+        MatcherResult::Accepted,
+        MatcherResult::Accepted,
+        MatcherResult::End(MatchResultValue::Vector(vec![
+          MatchResultValue::Token('('),
+          MatchResultValue::Option(Some(Box::new(MatchResultValue::Vector(vec![
+            MatchResultValue::Token('('),
+            MatchResultValue::Option(None),
+            MatchResultValue::Token(')'),
+          ])))),
+          MatchResultValue::Token(')'),
+        ])),
+      ],
+    );
+
+    parser.reset();
+
+    run_test(
+      &mut parser,
+      "())",
+      vec![
+        MatcherResult::Accepted,
+        MatcherResult::End(MatchResultValue::Vector(vec![
+          MatchResultValue::Token('('),
+          MatchResultValue::Option(None),
+          MatchResultValue::Token(')'),
+        ])),
+        MatcherResult::Rejected,
+      ],
+    );
+  }
+
+  #[test]
+  fn rules_refer_to_each_other() {
+    // list: [ item* ], item: a | list
+    let mut builder = Grammar::builder();
+    let list = builder.declare("list");
+    let item = builder.declare("item");
+    builder.define(
+      list,
+      Sequence::matcher(vec![
+        Terminal::matcher(|token: &char| *token == '['),
+        Optional::matcher(Loop::matcher(builder.rule(item))),
+        Terminal::matcher(|token: &char| *token == ']'),
+      ]),
+    );
+    builder.define(
+      item,
+      OneOf::matcher(vec![
+        Terminal::matcher(|token: &char| *token == 'a'),
+        builder.rule(list),
+      ]),
+    );
+    let grammar = builder.build().unwrap();
+    let mut parser = grammar.matcher(list);
+
+    let results: Vec<_> = "[a[[]a]]".chars().map(|c| parser.next(&c)).collect();
+
+    assert!(results[..7]
+      .iter()
+      .all(|r| matches!(r, MatcherResult::Accepted)));
+    assert!(matches!(results[7], MatcherResult::End(_)));
+  }
+
+  #[test]
+  fn left_recursion_is_an_error() {
+    let mut builder = Grammar::builder();
+    let sum = builder.declare("sum");
+    builder.define(
+      sum,
+      OneOf::matcher(vec![
+        Terminal::matcher(|token: &char| *token == 'a'),
+        Sequence::matcher(vec![
+          builder.rule(sum),
+          Terminal::matcher(|token: &char| *token == '+'),
+          Terminal::matcher(|token: &char| *token == 'a'),
+        ]),
+      ]),
+    );
+
+    assert_eq!(
+      builder.build().err(),
+      Some(String::from("Rule sum is left recursive: sum -> sum"))
+    );
+  }
+
+  #[test]
+  fn left_recursion_through_other_rules() {
+    // call: callee ( ), callee: a | call, `x` being optional doesn't make it right
+    let mut builder = Grammar::builder();
+    let call = builder.declare("call");
+    let callee = builder.declare("callee");
+    builder.define(
+      call,
+      Sequence::matcher(vec![
+        Optional::matcher(Terminal::matcher(|token: &char| *token == 'x')),
+        builder.rule(callee),
+        Terminal::matcher(|token: &char| *token == '('),
+        Terminal::matcher(|token: &char| *token == ')'),
+      ]),
+    );
+    builder.define(
+      callee,
+      OneOf::matcher(vec![
+        Terminal::matcher(|token: &char| *token == 'a'),
+        builder.rule(call),
+      ]),
+    );
+
+    assert_eq!(
+      builder.build().err(),
+      Some(String::from(
+        "Rule call is left recursive: call -> callee -> call"
+      ))
+    );
+  }
+
+  #[test]
+  fn rules_must_be_defined_and_not_empty() {
+    let mut builder = Grammar::<char>::builder();
+    builder.declare("missing");
+
+    assert_eq!(
+      builder.build().err(),
+      Some(String::from("Rule missing is declared but not defined"))
+    );
+
+    let mut builder = Grammar::builder();
+    let empty = builder.declare("empty");
+    builder.define(
+      empty,
+      Optional::matcher(Terminal::matcher(|token: &char| *token == 'a')),
+    );
+
+    assert_eq!(
+      builder.build().err(),
+      Some(String::from("Rule empty can match no tokens"))
+    );
+  }
+
+  /// Expected
+  fn labels(matcher: &MatcherType<char>) -> (Vec<String>, Option<String>) {
+    let expected = matcher.expected();
+    (
+      expected.labels.iter().map(|l| l.to_string()).collect(),
+      expected.after.map(|a| a.to_string()),
+    )
+  }
+
+  #[test]
+  fn expected_lists_the_live_labels() {
+    // [ item (, item)* ], item: a | b
+    let item = Label::matcher(
+      "item",
+      OneOf::matcher(vec![
+        Terminal::matcher(|token: &char| *token == 'a'),
+        Terminal::matcher(|token: &char| *token == 'b'),
+      ]),
+    );
+    let mut parser = Sequence::matcher(vec![
+      Label::matcher("[", Terminal::matcher(|token: &char| *token == '[')),
+      item.clone(),
+      Optional::matcher(Loop::matcher(Sequence::matcher(vec![
+        Label::matcher(",", Terminal::matcher(|token: &char| *token == ',')),
+        item,
+      ]))),
+      Label::matcher("]", Terminal::matcher(|token: &char| *token == ']')),
+    ]);
+
+    assert_eq!(labels(&parser), (vec![String::from("[")], None));
+    parser.next(&'[');
+    assert_eq!(labels(&parser), (vec![String::from("item")], None));
+    parser.next(&'a');
+    assert_eq!(
+      labels(&parser),
+      (
+        vec![String::from(","), String::from("]")],
+        Some(String::from("item"))
+      )
+    );
+    parser.next(&',');
+    assert_eq!(labels(&parser), (vec![String::from("item")], None));
+    assert_eq!(parser.next(&']'), MatcherResult::Rejected);
+    assert_eq!(labels(&parser), (vec![], None));
+  }
+
+  #[test]
+  fn expected_names_rules_not_started() {
+    // list: ( list? )
+    let mut builder = Grammar::builder();
+    let list = builder.declare("list");
+    builder.define(
+      list,
+      Sequence::matcher(vec![
+        Label::matcher("(", Terminal::matcher(|token: &char| *token == '(')),
+        Optional::matcher(builder.rule(list)),
+        Label::matcher(")", Terminal::matcher(|token: &char| *token == ')')),
+      ]),
+    );
+    let grammar = builder.build().unwrap();
+    let mut parser = grammar.matcher(list);
+
+    assert_eq!(labels(&parser), (vec![String::from("list")], None));
+    parser.next(&'(');
+    parser.next(&'(');
+    parser.next(&')');
+    assert_eq!(
+      labels(&parser),
+      (vec![String::from(")")], Some(String::from("list")))
+    );
+    assert_eq!(parser.expected().one_of(), ")");
+  }
+
+  /// Spans
+  #[derive(Clone, Debug, PartialEq)]
+  struct At(usize); // A token one byte long at an offset
+
+  impl Spanned for At {
+    fn span(&self) -> Span {
+      Span::new(self.0, self.0 + 1)
+    }
+  }
+
+  #[test]
+  fn values_have_the_span_of_their_tokens() {
+    let token = |offset| MatchResultValue::Token(At(offset));
+    let none = || MatchResultValue::Option(None);
+    let value = MatchResultValue::Vector(vec![
+      none(),
+      token(2),
+      MatchResultValue::Branch(1, Box::new(token(4))),
+      MatchResultValue::Option(Some(Box::new(MatchResultValue::Vector(vec![
+        token(6),
+        none(),
+      ])))),
+      none(),
+    ]);
+
+    assert_eq!(value.span(), Some(Span::new(2, 7)));
+    assert_eq!(none().span(), None);
+    assert_eq!(MatchResultValue::<At>::Vector(vec![none()]).span(), None);
+  }
+
+  #[test]
+  fn map_with_span() {
+    let mut parser = sequence!(
+      TypedMatcher::terminal(|token: &At| token.0 == 4),
+      TypedMatcher::terminal(|token: &At| token.0 == 5).optional(),
+    )
+    .map_with_span(|(first, _), span| (first.0, span));
+
+    parser.next(&At(4));
+    match parser.next(&At(5)) {
+      MatcherResult::End(value) => assert_eq!(parser.value(&value), (4, Span::new(4, 6))),
+      r => panic!("ended with {:?}", r),
+    }
+  }
+
+  /// Typed matchers
+  #[test]
+  fn typed_sequence_builds_a_tuple() {
+    let digit = || TypedMatcher::terminal(|token: &char| token.is_ascii_digit());
+    let mut parser = sequence!(
+      TypedMatcher::terminal(|token: &char| *token == '-').optional(),
+      digit().repeat(),
+      sequence!(
+        TypedMatcher::terminal(|token: &char| *token == '.'),
+        digit()
+      )
+      .optional(),
+    )
+    .map(|(minus, digits, decimal)| {
+      let digits: String = digits.into_iter().collect();
+      (minus.is_some(), digits, decimal.map(|(_, d)| d))
+    });
+
+    assert_eq!(
+      typed_value(&mut parser, "-12.5"),
+      (true, String::from("12"), Some('5'))
+    );
+    parser.reset();
+    assert_eq!(
+      typed_value(&mut parser, "7"),
+      (false, String::from("7"), None)
+    );
+  }
+
+  #[test]
+  fn typed_one_of_gets_the_value_of_its_branch() {
+    let mut parser = TypedMatcher::one_of(vec![
+      TypedMatcher::terminal(|token: &char| *token == 'a').map(|_| 1),
+      sequence!(
+        TypedMatcher::terminal(|token: &char| *token == 'b'),
+        TypedMatcher::terminal(|token: &char| *token == 'c'),
+      )
+      .map(|_| 2),
+    ]);
+
+    assert_eq!(typed_value(&mut parser, "bc"), 2);
+    parser.reset();
+    assert_eq!(typed_value(&mut parser, "a"), 1);
+  }
+
+  #[test]
+  fn typed_rule_refers_to_itself() {
+    // depth: ( depth? ), how many parentheses are nested
+    let mut builder = Grammar::builder();
+    let depth = builder.declare_typed("depth");
+    builder.define_typed(
+      &depth,
+      sequence!(
+        TypedMatcher::terminal(|token: &char| *token == '('),
+        builder.typed_rule(&depth).optional(),
+        TypedMatcher::terminal(|token: &char| *token == ')'),
+      )
+      .map(|(_, inner, _)| inner.unwrap_or(0) + 1),
+    );
+    let grammar = builder.build().unwrap();
+
+    assert_eq!(typed_value(&mut grammar.typed_matcher(&depth), "((()))"), 3);
+    assert_eq!(typed_value(&mut grammar.typed_matcher(&depth), "()"), 1);
+  }
+
+  /// Operators
+  // Evaluates digits joined by - + * ^, with a prefix - and a postfix !, and parentheses
+  fn arithmetic(text: &str) -> i64 {
+    let op = |c: fn(&char) -> bool| TypedMatcher::terminal(c);
+    let mut builder = Grammar::builder();
+    let expression = builder.declare_typed("expression");
+    let digit = op(|c| c.is_ascii_digit()).map(|c| c.to_digit(10).unwrap() as i64);
+    let parenthesized = sequence!(
+      op(|c| *c == '('),
+      builder.typed_rule(&expression),
+      op(|c| *c == ')')
+    )
+    .map(|(_, value, _)| value);
+    builder.define_typed(
+      &expression,
+      TypedOperators::new(TypedMatcher::one_of(vec![digit, parenthesized]))
+        .infix(1, Associativity::Left, op(|c| *c == '-'), |l, _, r| l - r)
+        .infix(1, Associativity::Left, op(|c| *c == '+'), |l, _, r| l + r)
+        .infix(2, Associativity::Left, op(|c| *c == '*'), |l, _, r| l * r)
+        .infix(4, Associativity::Right, op(|c| *c == '^'), |l, _, r| {
+          l.pow(r as u32)
+        })
+        .prefix(3, op(|c| *c == '-'), |_, operand| -operand)
+        .postfix(5, op(|c| *c == '!'), |operand, _| (1..=operand).product())
+        .matcher(),
+    );
+    let grammar = builder.build().unwrap();
+    typed_value(&mut grammar.typed_matcher(&expression), text)
+  }
+
+  #[test]
+  fn operators_by_precedence() {
+    assert_eq!(arithmetic("1+2*3"), 7);
+    assert_eq!(arithmetic("2*3+1"), 7);
+    assert_eq!(arithmetic("1+2*3^2*2-1"), 36);
+    assert_eq!(arithmetic("-2^2"), -4);
+    assert_eq!(arithmetic("-3!"), -6);
+    assert_eq!(arithmetic("2*3!"), 12);
+  }
+
+  #[test]
+  fn operators_by_associativity() {
+    assert_eq!(arithmetic("9-3-2"), 4);
+    assert_eq!(arithmetic("2^3^2"), 512);
+    assert_eq!(arithmetic("--3"), 3);
+    assert_eq!(arithmetic("3-(2-1)"), 2);
+    assert_eq!(arithmetic("(2^3)^2"), 64);
+  }
+
+  #[test]
+  fn operators_reject_what_cant_follow() {
+    let mut parser = Operators::matcher(
+      Terminal::matcher(|c: &char| c.is_ascii_digit()),
+      vec![Operator::new(
+        Fixity::Infix(Associativity::Left),
+        1,
+        Terminal::matcher(|c: &char| *c == '+'),
+      )],
+    );
+
+    assert!(matches!(parser.next(&'1'), MatcherResult::Value(_)));
+    assert_eq!(parser.next(&'+'), MatcherResult::Accepted);
+    assert_eq!(parser.next(&'+'), MatcherResult::Rejected);
+
+    parser.reset();
+    assert_eq!(parser.next(&'+'), MatcherResult::Rejected);
+  }
+
+  #[test]
+  fn operators_reject_excluded_operands() {
+    let mut parser = Operators::matcher(
+      Terminal::matcher(|c: &char| c.is_ascii_digit()),
+      vec![
+        Operator::new(
+          Fixity::Infix(Associativity::Right),
+          1,
+          Terminal::matcher(|c: &char| *c == '^'),
+        )
+        .excluding_left(2),
+        Operator::new(Fixity::Prefix, 2, Terminal::matcher(|c: &char| *c == '-')),
+      ],
+    );
+
+    assert!(matches!(parser.next(&'-'), MatcherResult::Accepted));
+    assert!(matches!(parser.next(&'1'), MatcherResult::Value(_)));
+    assert_eq!(parser.next(&'^'), MatcherResult::Rejected);
+
+    parser.reset();
+    for c in "1^-".chars() {
+      parser.next(&c);
+    }
+    assert!(matches!(parser.next(&'1'), MatcherResult::Value(_)));
+  }
+
   /// Utils
   fn run_test(matcher: &mut MatcherType<char>, sequence: &str, expect: Vec<MatcherResult<char>>) {
     assert_eq!(sequence.len(), expect.len());
@@ -554,4 +995,15 @@ mod parser_tests {
       assert_eq!(matcher.next(&c), expect[i], "failed on index {}", i);
     }
   }
+
+  fn typed_value<'a, Out: 'a>(matcher: &mut TypedMatcher<'a, char, Out>, sequence: &str) -> Out {
+    let mut result = MatcherResult::Rejected;
+    for c in sequence.chars() {
+      result = matcher.next(&c);
+    }
+    match result {
+      MatcherResult::End(value) | MatcherResult::Value(value) => matcher.value(&value),
+      r => panic!("{} ended with {:?}", sequence, r),
+    }
+  }
 }
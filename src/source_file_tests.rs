@@ -0,0 +1,134 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod source_file_tests {
+  use crate::ast::source_file::SourceFile;
+  use crate::lexer::Lexer;
+
+  fn parse(text: &str) -> String {
+    format!("{:?}", SourceFile::from(Lexer::from_text(text)))
+  }
+
+  #[test]
+  fn contextual_keywords_as_imported_names() {
+    let printed = parse("import { from } from 'x';\nimport type from 'y'\nimport * as as from 'z'");
+
+    assert!(printed.contains("NamedImport { original: \"from\", alias: None, span"));
+    assert!(printed.contains("default: Some(\"type\")"));
+    assert!(printed.contains("NamespaceImport(\"as\")"));
+  }
+
+  #[test]
+  fn contextual_keywords_as_parameters() {
+    let printed = parse("function type(as, of?: string) {}");
+
+    assert!(printed.contains("identifier: \"type\""));
+    assert!(printed.contains("identifier: \"as\""));
+    assert!(printed.contains("identifier: \"of\""));
+  }
+
+  #[test]
+  fn nested_types() {
+    let printed = parse("function f<T extends Array<Promise<T>>>(a: Map<string, Array<T>>) {}");
+
+    assert!(printed.contains(
+      "extends: Some(TypeDefinition { name: \"Array\", arguments: [TypeDefinition { name: \"Promise\", arguments: [TypeDefinition { name: \"T\", arguments: [], span: Span { start: 35, end: 36 } }], span: Span { start: 27, end: 37 } }], span: Span { start: 21, end: 38 } })"
+    ));
+    assert!(printed.contains(
+      "definition: Some(TypeDefinition { name: \"Map\", arguments: [TypeDefinition { name: \"string\", arguments: [], span: Span { start: 47, end: 53 } }, TypeDefinition { name: \"Array\", arguments: [TypeDefinition { name: \"T\", arguments: [], span: Span { start: 61, end: 62 } }], span: Span { start: 55, end: 63 } }], span: Span { start: 43, end: 64 } })"
+    ));
+  }
+
+  #[test]
+  fn parenthesized_expressions() {
+    let printed = parse("function f(a = ((b)), c = d) {}");
+
+    assert!(printed.contains("identifier: \"a\""));
+    assert!(printed.contains("identifier: \"c\""));
+  }
+
+  #[test]
+  fn initializers_are_read_from_the_initializer() {
+    let printed = parse("function f(a: T, b = c) {}");
+
+    assert!(printed.contains(
+      "identifier: \"a\", definition: Some(TypeDefinition { name: \"T\", arguments: [], span: Span { start: 14, end: 15 } }), optional: false, initializer: None"
+    ));
+    assert!(printed.contains(
+      "identifier: \"b\", definition: None, optional: false, initializer: Some(Expression { kind: Identifier(\"c\"), span: Span { start: 21, end: 22 } })"
+    ));
+  }
+
+  #[test]
+  fn nodes_have_the_span_of_their_tokens() {
+    // Separators and the statement's semicolon are left out
+    let printed = parse("import { a, b: c } from 'x';\nfunction f<T, U>(d?, e) {}");
+
+    assert!(printed.contains("original: \"a\", alias: None, span: Span { start: 9, end: 10 }"));
+    assert!(
+      printed.contains("original: \"b\", alias: Some(\"c\"), span: Span { start: 12, end: 16 }")
+    );
+    assert!(printed.contains("}])), span: Span { start: 0, end: 27 } }"));
+    assert!(printed.contains("identifier: \"T\", extends: None, span: Span { start: 40, end: 41 }"));
+    assert!(printed.contains("identifier: \"U\", extends: None, span: Span { start: 43, end: 44 }"));
+    assert!(printed.contains("initializer: None, span: Span { start: 46, end: 48 }"));
+    assert!(printed.contains("initializer: None, span: Span { start: 50, end: 51 }"));
+    assert!(printed.contains("}], span: Span { start: 29, end: 55 } }"));
+  }
+
+  #[test]
+  fn expressions_as_initializers() {
+    let printed = parse("function f(a = b + c * d, e = f(g)) {}");
+
+    assert!(printed.contains(
+      "initializer: Some(Expression { kind: Binary { left: Expression { kind: Identifier(\"b\")"
+    ));
+    assert!(printed.contains("operator: Plus, right: Expression { kind: Binary {"));
+    assert!(printed.contains(
+      "initializer: Some(Expression { kind: Call { callee: Expression { kind: Identifier(\"f\")"
+    ));
+  }
+
+  #[test]
+  fn type_arguments_in_initializers() {
+    let printed = parse("function f(a = x as Array<Array<T>>) {}");
+
+    assert!(printed.contains("initializer: Some(Expression { kind: As {"));
+    assert!(printed.contains("span: Span { start: 15, end: 35 } })"));
+  }
+
+  #[test]
+  #[should_panic(expected = "line: 1 col: 19 expected `,`, identifier or `)`, found `**`")]
+  fn unary_operand_of_exponent() {
+    parse("function f(a = -b ** c) {}");
+  }
+
+  #[test]
+  #[should_panic(expected = "line: 1 col: 23 expected `,`, identifier or `)`, found `||`")]
+  fn coalesce_mixed_with_logical_operators() {
+    parse("function f(a = b ?? c || d) {}");
+  }
+
+  #[test]
+  #[should_panic(expected = "line: 1 col: 12 expected identifier, found `class`")]
+  fn reserved_words_are_not_parameters() {
+    parse("function f(class) {}");
+  }
+
+  #[test]
+  #[should_panic(expected = "expected `:`, `,` or `}` after import specifier, found `b`")]
+  fn expected_tokens_after_a_construct() {
+    parse("import { a b } from 'x'");
+  }
+
+  #[test]
+  #[should_panic(expected = "expected import specifier, found `}`")]
+  fn expected_construct_after_a_token() {
+    parse("import { a, } from 'x'");
+  }
+
+  #[test]
+  #[should_panic(expected = "expected `<`, `,` or `>` after type, found `b`")]
+  fn expected_tokens_after_a_rule() {
+    parse("function f(a: Map<K b) {}");
+  }
+}
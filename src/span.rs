@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+/// Range in the source text, in byte offsets: `&text[span.start..span.end]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Self {
+    Span { start, end }
+  }
+
+  pub fn len(&self) -> usize {
+    self.end - self.start
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.start == self.end
+  }
+
+  /// From the start of `self` to the end of `other`
+  pub fn to(&self, other: Span) -> Span {
+    Span::new(self.start, other.end)
+  }
+}
+
+/// Something read from the source text, where it was read
+pub trait Spanned {
+  fn span(&self) -> Span;
+}
+
+/**
+ * The text of `span` replaced by `new_len` bytes of new text.
+ * `span` is in the text before the edit: an insertion has an empty span, a deletion `new_len` 0.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEdit {
+  pub span: Span,
+  pub new_len: usize,
+}
+
+impl TextEdit {
+  pub fn new(span: Span, new_len: usize) -> Self {
+    TextEdit { span, new_len }
+  }
+
+  /// End of the new text, in the text after the edit
+  pub fn new_end(&self) -> usize {
+    self.span.start + self.new_len
+  }
+
+  /// Where an offset after the edited span moves to in the text after the edit
+  pub fn shift(&self, offset: usize) -> usize {
+    offset - self.span.len() + self.new_len
+  }
+}
+
+/// Zero based position, as LSP clients expect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+  pub line: usize,
+  pub col: usize,
+}
+
+/**
+ * What a column counts:
+ * Utf8 => bytes
+ * Utf16 => UTF-16 code units, used by LSP clients and JS strings
+ * Chars => unicode scalar values
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  Utf8,
+  Utf16,
+  Chars,
+}
+
+/**
+ * Converts byte offsets to line/column and back.
+ * Only the start of every line and the position of non-ASCII chars are stored,
+ * lines with only ASCII chars have the same column in every encoding.
+ * `\n`, `\r\n` and `\r` are line breaks.
+ */
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+  line_starts: Vec<usize>,
+  wide_chars: HashMap<usize, Vec<WideChar>>, // line => wide chars, sorted by column
+  len: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WideChar {
+  col: usize, // in bytes from the start of the line
+  len_utf8: usize,
+  len_utf16: usize,
+}
+
+impl WideChar {
+  fn len(&self, encoding: Encoding) -> usize {
+    match encoding {
+      Encoding::Utf8 => self.len_utf8,
+      Encoding::Utf16 => self.len_utf16,
+      Encoding::Chars => 1,
+    }
+  }
+}
+
+impl LineIndex {
+  pub fn new(text: &str) -> Self {
+    let mut line_starts = vec![0];
+    let mut wide_chars: HashMap<usize, Vec<WideChar>> = HashMap::new();
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+      match c {
+        '\n' => line_starts.push(offset + 1),
+        '\r' if !matches!(chars.peek(), Some((_, '\n'))) => line_starts.push(offset + 1),
+        c if !c.is_ascii() => {
+          let line = line_starts.len() - 1;
+          wide_chars.entry(line).or_default().push(WideChar {
+            col: offset - line_starts[line],
+            len_utf8: c.len_utf8(),
+            len_utf16: c.len_utf16(),
+          });
+        }
+        _ => {}
+      }
+    }
+
+    LineIndex {
+      line_starts,
+      wide_chars,
+      len: text.len(),
+    }
+  }
+
+  pub fn line_count(&self) -> usize {
+    self.line_starts.len()
+  }
+
+  pub fn line_col(&self, offset: usize, encoding: Encoding) -> LineCol {
+    let offset = offset.min(self.len);
+    let line = match self.line_starts.binary_search(&offset) {
+      Ok(line) => line,
+      Err(next_line) => next_line - 1,
+    };
+    let byte_col = offset - self.line_starts[line];
+
+    let col = match self.wide_chars.get(&line) {
+      Some(wide_chars) if encoding != Encoding::Utf8 => {
+        let mut col = byte_col;
+        for wide_char in wide_chars.iter().take_while(|w| w.col < byte_col) {
+          col = col - wide_char.len_utf8 + wide_char.len(encoding);
+        }
+        col
+      }
+      _ => byte_col,
+    };
+
+    LineCol { line, col }
+  }
+
+  /// Byte offset of a position, None if it's out of the text.
+  pub fn offset(&self, position: LineCol, encoding: Encoding) -> Option<usize> {
+    let line_start = *self.line_starts.get(position.line)?;
+    let line_end = match self.line_starts.get(position.line + 1) {
+      Some(next_start) => *next_start,
+      None => self.len,
+    };
+
+    let mut byte_col = position.col;
+    if let (Some(wide_chars), true) = (
+      self.wide_chars.get(&position.line),
+      encoding != Encoding::Utf8,
+    ) {
+      let mut col = 0; // in `encoding`
+      byte_col = 0;
+      for wide_char in wide_chars {
+        let ascii_run = wide_char.col - byte_col;
+        if col + ascii_run >= position.col {
+          break;
+        }
+        col += ascii_run + wide_char.len(encoding);
+        byte_col = wide_char.col + wide_char.len_utf8;
+        if col > position.col {
+          // The position points inside a char
+          return None;
+        }
+      }
+      byte_col += position.col - col;
+    }
+
+    let offset = line_start + byte_col;
+    if offset > line_end {
+      None
+    } else {
+      Some(offset)
+    }
+  }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod span_tests {
+  use crate::lexer::Lexer;
+  use crate::span::{Encoding, LineCol, LineIndex, Span};
+
+  #[test]
+  fn token_spans() {
+    let text = "const café = 'π';\n  <>😀</>";
+    let lexer = Lexer::from_text(text);
+    let spans: Vec<(Span, &str)> = lexer
+      .map(|t| (t.span, &text[t.span.start..t.span.end]))
+      .collect();
+
+    assert_eq!(
+      spans,
+      vec![
+        (Span::new(0, 5), "const"),
+        (Span::new(6, 11), "café"),
+        (Span::new(12, 13), "="),
+        (Span::new(14, 18), "'π'"),
+        (Span::new(18, 19), ";"),
+        (Span::new(22, 24), "<>"),
+        (Span::new(24, 28), "😀"),
+        (Span::new(28, 31), "</>"),
+      ]
+    );
+  }
+
+  #[test]
+  fn line_col_in_every_encoding() {
+    let index = LineIndex::new("ab\r\nc😀d\ré");
+    assert_eq!(index.line_count(), 3);
+
+    // `d` is after a 4 byte, 2 UTF-16 units char
+    let d = 9;
+    assert_eq!(
+      index.line_col(d, Encoding::Utf8),
+      LineCol { line: 1, col: 5 }
+    );
+    assert_eq!(
+      index.line_col(d, Encoding::Utf16),
+      LineCol { line: 1, col: 3 }
+    );
+    assert_eq!(
+      index.line_col(d, Encoding::Chars),
+      LineCol { line: 1, col: 2 }
+    );
+
+    assert_eq!(
+      index.line_col(0, Encoding::Utf16),
+      LineCol { line: 0, col: 0 }
+    );
+    assert_eq!(
+      index.line_col(11, Encoding::Utf16),
+      LineCol { line: 2, col: 0 }
+    );
+    assert_eq!(
+      index.line_col(13, Encoding::Utf16),
+      LineCol { line: 2, col: 1 }
+    );
+  }
+
+  #[test]
+  fn offset_from_line_col() {
+    let index = LineIndex::new("ab\r\nc😀d\ré");
+
+    assert_eq!(
+      index.offset(LineCol { line: 1, col: 3 }, Encoding::Utf16),
+      Some(9)
+    );
+    assert_eq!(
+      index.offset(LineCol { line: 1, col: 2 }, Encoding::Chars),
+      Some(9)
+    );
+    assert_eq!(
+      index.offset(LineCol { line: 1, col: 5 }, Encoding::Utf8),
+      Some(9)
+    );
+    assert_eq!(
+      index.offset(LineCol { line: 1, col: 1 }, Encoding::Utf16),
+      Some(5)
+    );
+    assert_eq!(
+      index.offset(LineCol { line: 2, col: 1 }, Encoding::Utf16),
+      Some(13)
+    );
+
+    // In the middle of the surrogate pair
+    assert_eq!(
+      index.offset(LineCol { line: 1, col: 2 }, Encoding::Utf16),
+      None
+    );
+    assert_eq!(
+      index.offset(LineCol { line: 3, col: 0 }, Encoding::Utf16),
+      None
+    );
+  }
+}
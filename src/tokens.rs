@@ -1,48 +1,358 @@
+use crate::atom::Atom;
+use std::borrow::Cow;
+
+/// Literals borrow their text from the source, only decoded values with escapes are owned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+  pub kind: TokenKind,
+  pub value: Option<TokenValue<'a>>, // Only for identifiers, literals, templates and JSX text
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
-  Identifier(String),
-  Literal(Literal),
-  Symbol(String),
-  Keyword(String),
+pub enum TokenValue<'a> {
+  Identifier(Atom),
+  Literal(Literal<'a>),
+  Template(TemplateChunk<'a>),
+  JsxText(JsxText<'a>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Literal {
-  Integer(i32),
-  Str(String),
-  // BigInt(String),
+pub enum Literal<'a> {
   // Boolean(bool),
-  // Decimal(f64),
-  // Regex(String, String), // pattern, flags
   // Undefined,
   // Null,
+  Decimal(f64, &'a str),      // value, raw
+  BigInt(&'a str),            // raw, including the `n` suffix
+  Str(Cow<'a, str>, &'a str), // value, raw (without quotes)
+  Regex(&'a str, &'a str),    // pattern, flags
 }
 
-pub const VALID_SYMBOLS: &[&str] = &[
-  "=", "+", "-", "*", "/", "==", "!=", "===", "!==", "<", ">", "<=", ">=", ";", "=>", ",", ".",
-  "{", "}", "[", "]", "(", ")", "//", "/*", "*/", "`", "${", "?", ":", "&&", "||", "!", "</", "/>",
-  "<>", "</>", "?.", "??",
-];
-
-pub const KNOWN_KEYWORDS: &[&str] = &[
-  "import",
-  "from",
-  "as",
-  "function",
-  "return",
-  "while",
-  "if",
-  "do",
-  "typeof",
-  "delete",
-  "switch",
-  "break",
-  "continue",
-  "export",
-  "const",
-  "let",
-  "var",
-  "interface",
-  "extends",
-  "type",
-];
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateChunk<'a> {
+  pub cooked: Option<Cow<'a, str>>, // None if it has invalid escapes, only allowed on tagged templates
+  pub raw: Cow<'a, str>,            // Owned only if CRLF had to be normalized
+}
+
+/**
+ * Text between JSX tags. `value` is what React renders: lines trimmed and joined with a space,
+ * entities decoded. It's None when the text is only indentation between tags.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsxText<'a> {
+  pub raw: &'a str,
+  pub value: Option<Cow<'a, str>>,
+}
+
+impl<'a> Token<'a> {
+  pub fn new(kind: TokenKind) -> Self {
+    Token { kind, value: None }
+  }
+
+  pub fn identifier(name: Atom) -> Self {
+    Token {
+      kind: TokenKind::Identifier,
+      value: Some(TokenValue::Identifier(name)),
+    }
+  }
+
+  pub fn literal(literal: Literal<'a>) -> Self {
+    let kind = match literal {
+      Literal::Decimal(..) => TokenKind::NumericLiteral,
+      Literal::BigInt(_) => TokenKind::BigIntLiteral,
+      Literal::Str(..) => TokenKind::StringLiteral,
+      Literal::Regex(..) => TokenKind::RegexLiteral,
+    };
+    Token {
+      kind,
+      value: Some(TokenValue::Literal(literal)),
+    }
+  }
+
+  pub fn template(kind: TokenKind, chunk: TemplateChunk<'a>) -> Self {
+    Token {
+      kind,
+      value: Some(TokenValue::Template(chunk)),
+    }
+  }
+
+  pub fn jsx_text(text: JsxText<'a>) -> Self {
+    Token {
+      kind: TokenKind::JsxText,
+      value: Some(TokenValue::JsxText(text)),
+    }
+  }
+
+  /// Name of an identifier token, or of a contextual keyword used as an identifier
+  pub fn name(&self) -> Option<Atom> {
+    match self.value {
+      Some(TokenValue::Identifier(name)) => Some(name),
+      _ if self.kind.is_contextual_keyword() => Some(Atom::new(self.kind.as_str())),
+      _ => None,
+    }
+  }
+
+  /**
+   * `>` followed by other symbols is read greedily (`a >> b`, `a >= b`), but when closing nested
+   * type arguments (`Array<Array<T>>`) the parser needs each `>` on its own.
+   * Splits off the leading `>` of those symbols.
+   */
+  pub fn split_greater(&self) -> Option<(Token<'a>, Token<'a>)> {
+    let text = self.kind.as_str();
+    if !self.kind.is_punctuator() || text.len() == 1 || !text.starts_with('>') {
+      return None;
+    }
+    let rest = TokenKind::punctuator(&text[1..])?;
+    Some((Token::new(TokenKind::GreaterThan), Token::new(rest)))
+  }
+}
+
+macro_rules! token_kinds {
+  (
+    punctuators { $($p_name:ident => $p_text:literal,)* }
+    reserved_words { $($k_name:ident => $k_text:literal,)* }
+    strict_mode_reserved_words { $($s_name:ident => $s_text:literal,)* }
+    contextual_keywords { $($c_name:ident => $c_text:literal,)* }
+  ) => {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum TokenKind {
+      Identifier,
+      NumericLiteral,
+      BigIntLiteral,
+      StringLiteral,
+      RegexLiteral,
+      NoSubstitutionTemplate, // `text`
+      TemplateHead,           // `text${
+      TemplateMiddle,         // }text${
+      TemplateTail,           // }text`
+      JsxText,                // <a>text</a>
+      Error,                  // Text the lexer couldn't read, see its diagnostic
+      $($p_name,)*
+      $($k_name,)*
+      $($s_name,)*
+      $($c_name,)*
+    }
+
+    impl TokenKind {
+      pub fn punctuator(text: &str) -> Option<TokenKind> {
+        match text {
+          $($p_text => Some(TokenKind::$p_name),)*
+          _ => None,
+        }
+      }
+
+      pub fn keyword(text: &str) -> Option<TokenKind> {
+        match text {
+          $($k_text => Some(TokenKind::$k_name),)*
+          $($s_text => Some(TokenKind::$s_name),)*
+          $($c_text => Some(TokenKind::$c_name),)*
+          _ => None,
+        }
+      }
+
+      pub fn is_punctuator(self) -> bool {
+        matches!(self, $(TokenKind::$p_name)|*)
+      }
+
+      /// Never an identifier: `if`, `class`, `this`, `true`...
+      pub fn is_reserved_word(self) -> bool {
+        matches!(self, $(TokenKind::$k_name)|*)
+      }
+
+      /// Identifiers only in sloppy mode scripts: `let`, `yield`, `interface`...
+      pub fn is_strict_mode_reserved_word(self) -> bool {
+        matches!(self, $(TokenKind::$s_name)|*)
+      }
+
+      /// Keywords only in some places, identifiers everywhere else: `type`, `from`, `as`...
+      pub fn is_contextual_keyword(self) -> bool {
+        matches!(self, $(TokenKind::$c_name)|*)
+      }
+
+      /**
+       * Can name a binding or be referenced: identifiers and contextual keywords.
+       * Source files are modules, which are strict mode code, so `let` or `yield` can't.
+       */
+      pub fn is_identifier(self) -> bool {
+        self == TokenKind::Identifier || self.is_contextual_keyword()
+      }
+
+      pub fn as_str(self) -> &'static str {
+        match self {
+          TokenKind::Identifier => "identifier",
+          TokenKind::NumericLiteral => "numeric literal",
+          TokenKind::BigIntLiteral => "bigint literal",
+          TokenKind::StringLiteral => "string literal",
+          TokenKind::RegexLiteral => "regular expression literal",
+          TokenKind::NoSubstitutionTemplate
+          | TokenKind::TemplateHead
+          | TokenKind::TemplateMiddle
+          | TokenKind::TemplateTail => "template literal",
+          TokenKind::JsxText => "JSX text",
+          TokenKind::Error => "invalid token",
+          $(TokenKind::$p_name => $p_text,)*
+          $(TokenKind::$k_name => $k_text,)*
+          $(TokenKind::$s_name => $s_text,)*
+          $(TokenKind::$c_name => $c_text,)*
+        }
+      }
+    }
+  };
+}
+
+token_kinds! {
+  punctuators {
+    OpenBrace => "{",
+    CloseBrace => "}",
+    OpenParen => "(",
+    CloseParen => ")",
+    OpenBracket => "[",
+    CloseBracket => "]",
+    Dot => ".",
+    DotDotDot => "...",
+    Semicolon => ";",
+    Comma => ",",
+    LessThan => "<",
+    GreaterThan => ">",
+    LessThanEquals => "<=",
+    GreaterThanEquals => ">=",
+    EqualsEquals => "==",
+    ExclamationEquals => "!=",
+    EqualsEqualsEquals => "===",
+    ExclamationEqualsEquals => "!==",
+    EqualsGreaterThan => "=>",
+    Plus => "+",
+    Minus => "-",
+    Asterisk => "*",
+    AsteriskAsterisk => "**",
+    Slash => "/",
+    Percent => "%",
+    PlusPlus => "++",
+    MinusMinus => "--",
+    LessThanLessThan => "<<",
+    GreaterThanGreaterThan => ">>",
+    GreaterThanGreaterThanGreaterThan => ">>>",
+    Ampersand => "&",
+    Bar => "|",
+    Caret => "^",
+    Exclamation => "!",
+    Tilde => "~",
+    AmpersandAmpersand => "&&",
+    BarBar => "||",
+    Question => "?",
+    QuestionQuestion => "??",
+    QuestionDot => "?.",
+    Colon => ":",
+    At => "@",
+    Hash => "#",
+    Equals => "=",
+    PlusEquals => "+=",
+    MinusEquals => "-=",
+    AsteriskEquals => "*=",
+    AsteriskAsteriskEquals => "**=",
+    SlashEquals => "/=",
+    PercentEquals => "%=",
+    LessThanLessThanEquals => "<<=",
+    GreaterThanGreaterThanEquals => ">>=",
+    GreaterThanGreaterThanGreaterThanEquals => ">>>=",
+    AmpersandEquals => "&=",
+    BarEquals => "|=",
+    CaretEquals => "^=",
+    AmpersandAmpersandEquals => "&&=",
+    BarBarEquals => "||=",
+    QuestionQuestionEquals => "??=",
+    // JSX
+    LessThanSlash => "</",
+    SlashGreaterThan => "/>",
+    LessThanGreaterThan => "<>",
+    LessThanSlashGreaterThan => "</>",
+  }
+  reserved_words {
+    Break => "break",
+    Case => "case",
+    Catch => "catch",
+    Class => "class",
+    Const => "const",
+    Continue => "continue",
+    Debugger => "debugger",
+    Default => "default",
+    Delete => "delete",
+    Do => "do",
+    Else => "else",
+    Enum => "enum",
+    Export => "export",
+    Extends => "extends",
+    False => "false",
+    Finally => "finally",
+    For => "for",
+    Function => "function",
+    If => "if",
+    Import => "import",
+    In => "in",
+    Instanceof => "instanceof",
+    New => "new",
+    Null => "null",
+    Return => "return",
+    Super => "super",
+    Switch => "switch",
+    This => "this",
+    Throw => "throw",
+    True => "true",
+    Try => "try",
+    Typeof => "typeof",
+    Var => "var",
+    Void => "void",
+    While => "while",
+    With => "with",
+  }
+  strict_mode_reserved_words {
+    Implements => "implements",
+    Interface => "interface",
+    Let => "let",
+    Package => "package",
+    Private => "private",
+    Protected => "protected",
+    Public => "public",
+    Static => "static",
+    Yield => "yield",
+  }
+  contextual_keywords {
+    Abstract => "abstract",
+    Accessor => "accessor",
+    Any => "any",
+    As => "as",
+    Assert => "assert",
+    Asserts => "asserts",
+    Async => "async",
+    Await => "await",
+    Bigint => "bigint",
+    Boolean => "boolean",
+    Constructor => "constructor",
+    Declare => "declare",
+    From => "from",
+    Get => "get",
+    Global => "global",
+    Infer => "infer",
+    Intrinsic => "intrinsic",
+    Is => "is",
+    Keyof => "keyof",
+    Module => "module",
+    Namespace => "namespace",
+    Never => "never",
+    Number => "number",
+    Object => "object",
+    Of => "of",
+    Out => "out",
+    Override => "override",
+    Readonly => "readonly",
+    Require => "require",
+    Satisfies => "satisfies",
+    Set => "set",
+    String => "string",
+    Symbol => "symbol",
+    Type => "type",
+    Undefined => "undefined",
+    Unique => "unique",
+    Unknown => "unknown",
+    Using => "using",
+  }
+}